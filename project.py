@@ -50,7 +50,7 @@ results = run_jobs(jobs)
 
 data_dict = {}
 for job, result in results.items():
-    for cache in result:
+    for cache in result['caches']:
         data_dict[(job.job_id.rsplit('-', -1)[1], job.trace[0], cache['name'])] = cache
 
 cache_names = sorted(set(key[2] for key in data_dict.keys()))
@@ -0,0 +1,25 @@
+use super::{hash_ip, train, Predictor};
+
+const INDEX_BITS: u32 = 14; // 16K counters
+
+pub struct Bimodal {
+    counters: Vec<i8>,
+}
+
+impl Bimodal {
+    pub fn new() -> Self {
+        Bimodal {
+            counters: vec![0; 1 << INDEX_BITS],
+        }
+    }
+}
+
+impl Predictor for Bimodal {
+    fn predict(&mut self, ip: u64) -> bool {
+        self.counters[hash_ip(ip, INDEX_BITS)] >= 0
+    }
+
+    fn update(&mut self, ip: u64, taken: bool) {
+        train(&mut self.counters[hash_ip(ip, INDEX_BITS)], taken, -2, 1);
+    }
+}
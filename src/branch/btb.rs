@@ -0,0 +1,94 @@
+use std::collections::VecDeque;
+
+use serde::Serialize;
+
+use crate::{cpu::Cpu, simpoint};
+
+/// A set-associative LRU branch target buffer, looked up by taken branches
+pub struct Btb {
+    n_sets: usize,
+    /// Branch ip and target per set, most recently used first
+    sets: Vec<VecDeque<(u64, u64)>>,
+    n_ways: usize,
+    lookups: u64,
+    misses: u64,
+    wrong_targets: u64,
+}
+
+#[derive(Serialize)]
+pub struct BtbStats {
+    lookups: u64,
+    misses: u64,
+    wrong_targets: u64,
+    mpki: f64,
+    #[serde(skip)]
+    instructions: u64,
+}
+
+impl Btb {
+    pub fn new(n_sets: usize, n_ways: usize) -> Self {
+        assert!(n_sets.is_power_of_two());
+        Btb {
+            n_sets,
+            sets: vec![VecDeque::with_capacity(n_ways); n_sets],
+            n_ways,
+            lookups: 0,
+            misses: 0,
+            wrong_targets: 0,
+        }
+    }
+
+    pub fn access(&mut self, ip: u64, target: u64) {
+        self.lookups += 1;
+        let set = &mut self.sets[(ip >> 2) as usize & (self.n_sets - 1)];
+        match set.iter().position(|&(entry_ip, _)| entry_ip == ip) {
+            Some(way) => {
+                let (_, old_target) = set.remove(way).unwrap();
+                if old_target != target {
+                    self.wrong_targets += 1;
+                }
+            }
+            None => {
+                self.misses += 1;
+                if set.len() == self.n_ways {
+                    set.pop_back();
+                }
+            }
+        }
+        set.push_front((ip, target));
+    }
+
+    pub fn clear_stats(&mut self) {
+        self.lookups = 0;
+        self.misses = 0;
+        self.wrong_targets = 0;
+    }
+
+    pub fn make_stats(&self, cpu: &Cpu) -> BtbStats {
+        BtbStats::new(
+            [self.lookups, self.misses, self.wrong_targets, cpu.instrs()].map(|count| count as f64),
+        )
+    }
+}
+
+impl BtbStats {
+    /// Counts are fractional when they come from weighted regions
+    fn new(counts: [f64; 4]) -> Self {
+        let [lookups, misses, wrong_targets, instructions] = counts;
+        BtbStats {
+            lookups: lookups.round() as u64,
+            misses: misses.round() as u64,
+            wrong_targets: wrong_targets.round() as u64,
+            mpki: (misses + wrong_targets) * 1000f64 / instructions,
+            instructions: instructions.round() as u64,
+        }
+    }
+
+    /// Regions' counts summed by weight
+    pub fn weighted(regions: &[(f64, &BtbStats)]) -> Self {
+        let counts = |s: &BtbStats| [s.lookups, s.misses, s.wrong_targets, s.instructions];
+        BtbStats::new(std::array::from_fn(|idx| {
+            simpoint::sum(regions, |s| counts(s)[idx] as f64)
+        }))
+    }
+}
@@ -0,0 +1,34 @@
+use super::{hash_ip, train, Predictor};
+
+const INDEX_BITS: u32 = 14; // 16K counters, 14 bits of history
+
+pub struct Gshare {
+    counters: Vec<i8>,
+    history: u64,
+}
+
+impl Gshare {
+    pub fn new() -> Self {
+        Gshare {
+            counters: vec![0; 1 << INDEX_BITS],
+            history: 0,
+        }
+    }
+
+    fn index(&self, ip: u64) -> usize {
+        let mask = (1u64 << INDEX_BITS) - 1;
+        hash_ip(ip, INDEX_BITS) ^ (self.history & mask) as usize
+    }
+}
+
+impl Predictor for Gshare {
+    fn predict(&mut self, ip: u64) -> bool {
+        self.counters[self.index(ip)] >= 0
+    }
+
+    fn update(&mut self, ip: u64, taken: bool) {
+        let index = self.index(ip);
+        train(&mut self.counters[index], taken, -2, 1);
+        self.history = (self.history << 1) | taken as u64;
+    }
+}
@@ -0,0 +1,206 @@
+pub mod bimodal;
+pub mod btb;
+pub mod gshare;
+pub mod perceptron;
+pub mod tage;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{cpu::Cpu, simpoint, trace::Instr};
+
+use self::{bimodal::Bimodal, btb::Btb, gshare::Gshare, perceptron::Perceptron, tage::Tage};
+
+pub trait Predictor {
+    fn predict(&mut self, ip: u64) -> bool;
+    /// Called with the outcome right after `predict` for the same branch
+    fn update(&mut self, ip: u64, taken: bool);
+}
+
+#[derive(Deserialize, Clone)]
+pub struct BtbConfig {
+    sets: usize,
+    ways: usize,
+}
+
+#[derive(Deserialize, Clone)]
+pub struct BranchConfig {
+    predictors: Vec<String>,
+    btb: Option<BtbConfig>,
+}
+
+#[derive(Serialize)]
+pub struct PredictorStats {
+    name: String,
+    mispredictions: u64,
+    accuracy: f64,
+    mpki: f64,
+}
+
+#[derive(Serialize)]
+pub struct BranchStats {
+    branches: u64,
+    taken: u64,
+    predictors: Vec<PredictorStats>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    btb: Option<btb::BtbStats>,
+    #[serde(skip)]
+    instructions: u64,
+}
+
+struct Entry {
+    name: String,
+    predictor: Box<dyn Predictor>,
+    mispredictions: u64,
+}
+
+/// Runs every configured predictor side by side over the trace's branches
+pub struct Branch {
+    predictors: Vec<Entry>,
+    btb: Option<Btb>,
+    /// A taken branch waiting on the next instruction to learn its target
+    pending_target: Option<u64>,
+    branches: u64,
+    taken: u64,
+}
+
+impl Branch {
+    pub fn new(config: BranchConfig) -> Self {
+        let predictors = config
+            .predictors
+            .into_iter()
+            .map(|name| {
+                let predictor = match name.as_str() {
+                    "bimodal" => Box::new(Bimodal::new()) as Box<dyn Predictor>,
+                    "gshare" => Box::new(Gshare::new()) as Box<dyn Predictor>,
+                    "perceptron" => Box::new(Perceptron::new()) as Box<dyn Predictor>,
+                    "tage" => Box::new(Tage::new()) as Box<dyn Predictor>,
+                    _ => panic!("Unrecognized branch predictor: {}", name),
+                };
+                Entry {
+                    name,
+                    predictor,
+                    mispredictions: 0,
+                }
+            })
+            .collect();
+        Branch {
+            predictors,
+            btb: config.btb.map(|btb| Btb::new(btb.sets, btb.ways)),
+            pending_target: None,
+            branches: 0,
+            taken: 0,
+        }
+    }
+
+    pub fn observe(&mut self, instr: &Instr) {
+        if let Some(branch_ip) = self.pending_target.take() {
+            if let Some(btb) = &mut self.btb {
+                btb.access(branch_ip, instr.ip);
+            }
+        }
+        if instr.is_branch == 0 {
+            return;
+        }
+
+        let taken = instr.branch_taken != 0;
+        self.branches += 1;
+        self.taken += taken as u64;
+        for entry in &mut self.predictors {
+            if entry.predictor.predict(instr.ip) != taken {
+                entry.mispredictions += 1;
+            }
+            entry.predictor.update(instr.ip, taken);
+        }
+        if taken {
+            self.pending_target = Some(instr.ip);
+        }
+    }
+
+    pub fn clear_stats(&mut self) {
+        self.branches = 0;
+        self.taken = 0;
+        for entry in &mut self.predictors {
+            entry.mispredictions = 0;
+        }
+        if let Some(btb) = &mut self.btb {
+            btb.clear_stats();
+        }
+    }
+
+    pub fn make_stats(&self, cpu: &Cpu) -> BranchStats {
+        let branches = self.branches as f64;
+        let instructions = cpu.instrs() as f64;
+        BranchStats {
+            branches: self.branches,
+            taken: self.taken,
+            predictors: self
+                .predictors
+                .iter()
+                .map(|entry| {
+                    let mispredictions = entry.mispredictions as f64;
+                    PredictorStats::new(entry.name.clone(), mispredictions, branches, instructions)
+                })
+                .collect(),
+            btb: self.btb.as_ref().map(|btb| btb.make_stats(cpu)),
+            instructions: cpu.instrs(),
+        }
+    }
+}
+
+impl PredictorStats {
+    /// Counts are fractional when they come from weighted regions
+    fn new(name: String, mispredictions: f64, branches: f64, instructions: f64) -> Self {
+        PredictorStats {
+            name,
+            mispredictions: mispredictions.round() as u64,
+            accuracy: 1f64 - mispredictions / branches,
+            mpki: mispredictions * 1000f64 / instructions,
+        }
+    }
+}
+
+impl BranchStats {
+    /// Regions' counts summed by weight
+    pub fn weighted(regions: &[(f64, &BranchStats)]) -> Self {
+        let sum = |count: fn(&BranchStats) -> u64| simpoint::sum(regions, |s| count(s) as f64);
+        let branches = sum(|s| s.branches);
+        let instructions = sum(|s| s.instructions);
+        let first = regions[0].1;
+        BranchStats {
+            branches: branches.round() as u64,
+            taken: sum(|s| s.taken).round() as u64,
+            predictors: (0..first.predictors.len())
+                .map(|idx| {
+                    let mispredictions =
+                        simpoint::sum(regions, |s| s.predictors[idx].mispredictions as f64);
+                    let name = first.predictors[idx].name.clone();
+                    PredictorStats::new(name, mispredictions, branches, instructions)
+                })
+                .collect(),
+            btb: first.btb.as_ref().map(|_| {
+                let btbs: Vec<(f64, &btb::BtbStats)> = regions
+                    .iter()
+                    .map(|&(scale, s)| (scale, s.btb.as_ref().unwrap()))
+                    .collect();
+                btb::BtbStats::weighted(&btbs)
+            }),
+            instructions: instructions.round() as u64,
+        }
+    }
+}
+
+/// Saturating counter update within `min..=max`
+fn train(counter: &mut i8, taken: bool, min: i8, max: i8) {
+    if taken {
+        *counter = (*counter + 1).min(max);
+    } else {
+        *counter = (*counter - 1).max(min);
+    }
+}
+
+/// Folds the low `bits` bits of `ip` with the bits above them
+fn hash_ip(ip: u64, bits: u32) -> usize {
+    let mask = (1u64 << bits) - 1;
+    let ip = ip >> 2;
+    ((ip ^ (ip >> bits) ^ (ip >> (2 * bits))) & mask) as usize
+}
@@ -0,0 +1,59 @@
+use super::{hash_ip, Predictor};
+
+const INDEX_BITS: u32 = 10; // 1K perceptrons
+const HISTORY: usize = 32;
+// Training threshold from Jimenez and Lin, 1.93 * history + 14
+const THRESHOLD: i32 = (1.93 * HISTORY as f64 + 14.0) as i32;
+
+pub struct Perceptron {
+    /// Bias weight followed by one weight per history bit
+    weights: Vec<[i8; HISTORY + 1]>,
+    history: u64,
+    last_output: i32,
+}
+
+impl Perceptron {
+    pub fn new() -> Self {
+        Perceptron {
+            weights: vec![[0; HISTORY + 1]; 1 << INDEX_BITS],
+            history: 0,
+            last_output: 0,
+        }
+    }
+
+    fn history_sign(&self, bit: usize) -> i32 {
+        if (self.history >> bit) & 1 == 1 {
+            1
+        } else {
+            -1
+        }
+    }
+}
+
+impl Predictor for Perceptron {
+    fn predict(&mut self, ip: u64) -> bool {
+        let weights = &self.weights[hash_ip(ip, INDEX_BITS)];
+        let output = weights[0] as i32
+            + (0..HISTORY)
+                .map(|bit| weights[bit + 1] as i32 * self.history_sign(bit))
+                .sum::<i32>();
+        self.last_output = output;
+        output >= 0
+    }
+
+    fn update(&mut self, ip: u64, taken: bool) {
+        let output = self.last_output;
+        if (output >= 0) != taken || output.abs() <= THRESHOLD {
+            let direction: i8 = if taken { 1 } else { -1 };
+            let signs: Vec<i8> = (0..HISTORY)
+                .map(|bit| self.history_sign(bit) as i8)
+                .collect();
+            let weights = &mut self.weights[hash_ip(ip, INDEX_BITS)];
+            weights[0] = weights[0].saturating_add(direction);
+            for (weight, sign) in weights[1..].iter_mut().zip(signs) {
+                *weight = weight.saturating_add(direction * sign);
+            }
+        }
+        self.history = (self.history << 1) | taken as u64;
+    }
+}
@@ -0,0 +1,156 @@
+use super::{hash_ip, train, Predictor};
+
+const BASE_BITS: u32 = 13; // 8K bimodal counters
+const TABLE_BITS: u32 = 10; // 1K entries per tagged table
+const TAG_BITS: u32 = 9;
+const HISTORY_LENGTHS: [u32; 4] = [8, 16, 32, 64];
+const USEFUL_RESET_PERIOD: u64 = 1 << 18;
+
+#[derive(Clone, Copy, Default)]
+struct TaggedEntry {
+    // Entries start out empty, and an empty one must not match a tag of 0
+    valid: bool,
+    tag: u16,
+    counter: i8,
+    useful: u8,
+}
+
+/// A small TAGE: a bimodal base predictor and four tagged tables indexed with
+/// geometrically longer global histories
+pub struct Tage {
+    base: Vec<i8>,
+    tables: Vec<Vec<TaggedEntry>>,
+    history: u64,
+    branches: u64,
+    rng: fastrand::Rng,
+    // Decided by the last call to predict
+    provider: Option<usize>,
+    alt_prediction: bool,
+    prediction: bool,
+}
+
+impl Tage {
+    pub fn new() -> Self {
+        Tage {
+            base: vec![0; 1 << BASE_BITS],
+            tables: vec![vec![TaggedEntry::default(); 1 << TABLE_BITS]; HISTORY_LENGTHS.len()],
+            history: 0,
+            branches: 0,
+            rng: fastrand::Rng::with_seed(0),
+            provider: None,
+            alt_prediction: false,
+            prediction: false,
+        }
+    }
+
+    /// The last `length` history bits, folded down to `bits` bits
+    fn folded_history(&self, length: u32, bits: u32) -> u64 {
+        let mut history = if length == 64 {
+            self.history
+        } else {
+            self.history & ((1 << length) - 1)
+        };
+        let mut folded = 0;
+        while history != 0 {
+            folded ^= history & ((1 << bits) - 1);
+            history >>= bits;
+        }
+        folded
+    }
+
+    fn index(&self, table: usize, ip: u64) -> usize {
+        let length = HISTORY_LENGTHS[table];
+        hash_ip(ip, TABLE_BITS) ^ self.folded_history(length, TABLE_BITS) as usize
+    }
+
+    fn tag(&self, table: usize, ip: u64) -> u16 {
+        let length = HISTORY_LENGTHS[table];
+        let tag =
+            (ip >> 2) ^ (ip >> (2 + TAG_BITS)) ^ (self.folded_history(length, TAG_BITS - 1) << 1);
+        (tag & ((1 << TAG_BITS) - 1)) as u16
+    }
+
+    fn lookup(&self, table: usize, ip: u64) -> Option<&TaggedEntry> {
+        let entry = &self.tables[table][self.index(table, ip)];
+        (entry.valid && entry.tag == self.tag(table, ip)).then_some(entry)
+    }
+}
+
+impl Predictor for Tage {
+    fn predict(&mut self, ip: u64) -> bool {
+        let base_prediction = self.base[hash_ip(ip, BASE_BITS)] >= 0;
+        let mut hits = (0..self.tables.len())
+            .rev()
+            .filter(|&t| self.lookup(t, ip).is_some());
+        let provider = hits.next();
+        let alt = hits.next();
+
+        let predict_with = |table: Option<usize>| {
+            table.map_or(base_prediction, |t| {
+                self.lookup(t, ip).unwrap().counter >= 0
+            })
+        };
+        let alt_prediction = predict_with(alt);
+        let prediction = predict_with(provider);
+        self.provider = provider;
+        self.alt_prediction = alt_prediction;
+        self.prediction = prediction;
+        self.prediction
+    }
+
+    fn update(&mut self, ip: u64, taken: bool) {
+        match self.provider {
+            Some(table) => {
+                let index = self.index(table, ip);
+                let entry = &mut self.tables[table][index];
+                train(&mut entry.counter, taken, -4, 3);
+                if self.prediction != self.alt_prediction {
+                    if self.prediction == taken {
+                        entry.useful = (entry.useful + 1).min(3);
+                    } else {
+                        entry.useful = entry.useful.saturating_sub(1);
+                    }
+                }
+            }
+            None => train(&mut self.base[hash_ip(ip, BASE_BITS)], taken, -2, 1),
+        }
+
+        // On a misprediction, try to allocate in a table with a longer history
+        if self.prediction != taken {
+            let first = self.provider.map_or(0, |t| t + 1);
+            let free: Vec<usize> = (first..self.tables.len())
+                .filter(|&t| self.tables[t][self.index(t, ip)].useful == 0)
+                .collect();
+            if free.is_empty() {
+                for table in first..self.tables.len() {
+                    let index = self.index(table, ip);
+                    let entry = &mut self.tables[table][index];
+                    entry.useful = entry.useful.saturating_sub(1);
+                }
+            } else {
+                // Favour the shortest history, as TAGE does
+                let table = if free.len() > 1 && self.rng.u8(0..3) == 0 {
+                    free[1]
+                } else {
+                    free[0]
+                };
+                let index = self.index(table, ip);
+                let tag = self.tag(table, ip);
+                self.tables[table][index] = TaggedEntry {
+                    valid: true,
+                    tag,
+                    counter: if taken { 0 } else { -1 },
+                    useful: 0,
+                };
+            }
+        }
+
+        self.branches += 1;
+        if self.branches & (USEFUL_RESET_PERIOD - 1) == 0 {
+            for entry in self.tables.iter_mut().flatten() {
+                entry.useful >>= 1;
+            }
+        }
+        self.history = (self.history << 1) | taken as u64;
+    }
+}
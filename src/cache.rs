@@ -1,13 +1,17 @@
 use std::{
+    collections::BTreeMap,
     iter,
     ops::{Not, Range},
 };
 
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 
 use crate::{
     cpu::Cpu,
-    replace::{AccessResult, MakeS, Replace},
+    mshr::{Mshr, MshrStats},
+    prefetch::{PrefetchStats, Prefetcher},
+    replace::{opt::Recording, Access, AccessResult, Eviction, MakeS, Policy, Replace, Set},
+    simpoint,
 };
 
 #[derive(Debug)]
@@ -17,6 +21,78 @@ pub struct Addr {
     pub tag: usize,
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AccessKind {
+    Fetch,
+    Load,
+    Store,
+    /// A store that allocated in an upper level and is filling from below
+    Rfo,
+    Writeback,
+    /// A clean line evicted from an upper level into an exclusive cache
+    Victim,
+    /// A line a prefetcher asked for ahead of any demand
+    Prefetch,
+}
+
+impl AccessKind {
+    pub const ALL: [AccessKind; 7] = [
+        AccessKind::Fetch,
+        AccessKind::Load,
+        AccessKind::Store,
+        AccessKind::Rfo,
+        AccessKind::Writeback,
+        AccessKind::Victim,
+        AccessKind::Prefetch,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            AccessKind::Fetch => "fetch",
+            AccessKind::Load => "load",
+            AccessKind::Store => "store",
+            AccessKind::Rfo => "rfo",
+            AccessKind::Writeback => "writeback",
+            AccessKind::Victim => "victim",
+            AccessKind::Prefetch => "prefetch",
+        }
+    }
+
+    pub fn is_write(self) -> bool {
+        matches!(self, AccessKind::Store | AccessKind::Writeback)
+    }
+
+    pub fn is_demand(self) -> bool {
+        !matches!(
+            self,
+            AccessKind::Writeback | AccessKind::Victim | AccessKind::Prefetch
+        )
+    }
+
+    pub fn is_data(self) -> bool {
+        matches!(self, AccessKind::Load | AccessKind::Store | AccessKind::Rfo)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Inclusion {
+    /// Lines are filled here on every miss, regardless of the levels above
+    #[default]
+    Nine,
+    /// Every line above is also held here, evictions back-invalidate above
+    Inclusive,
+    /// Lines are only filled here when evicted from above
+    Exclusive,
+}
+
+#[derive(Debug)]
+pub struct Evicted {
+    pub set: usize,
+    pub tag: usize,
+    pub dirty: bool,
+}
+
 #[derive(Debug)]
 pub struct BitSection {
     shift: usize,
@@ -27,24 +103,214 @@ impl BitSection {
     fn apply(&self, num: usize) -> usize {
         (num >> self.shift) & self.mask
     }
+
+    fn place(&self, num: usize) -> usize {
+        (num & self.mask) << self.shift
+    }
+}
+
+#[derive(Serialize)]
+pub struct AccessStats {
+    misses: u64,
+    hits: u64,
+    mpki: f64,
 }
 
 #[derive(Serialize)]
 pub struct CacheStats {
     name: String,
+    /// The core a private cache belongs to, None if shared
+    core: Option<usize>,
     misses: u64,
     hits: u64,
     miss_rate: f64,
     mpki: f64,
+    instr_mpki: f64,
+    data_mpki: f64,
+    by_kind: BTreeMap<&'static str, AccessStats>,
     reuse: f64,
     lifetime: f64,
     efficiency: f64,
     efficiency_im: Vec<f64>,
+    writebacks_issued: u64,
+    writebacks_received: u64,
+    back_invalidations_issued: u64,
+    back_invalidations_received: u64,
+    amat: f64,
+    mshr: MshrStats,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    policy: Option<serde_json::Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    prefetch: Option<PrefetchStats>,
+    #[serde(skip)]
+    counts: CacheCounts,
 }
 
+/// What a cache's stats are worked out from, fractional when they come from
+/// weighted regions
+struct CacheCounts {
+    instructions: f64,
+    misses: [f64; AccessKind::ALL.len()],
+    hits: [f64; AccessKind::ALL.len()],
+    demand_cycles: f64,
+    allocations: f64,
+    /// Cycles each block spent holding a line before and after its last use
+    live: Vec<f64>,
+    dead: Vec<f64>,
+    writebacks_issued: f64,
+    back_invalidations_issued: f64,
+    back_invalidations_received: f64,
+}
+
+/// The `counts` of the kinds of access `filter` picks, summed
+fn count<T: Copy + iter::Sum>(counts: &[T], filter: fn(AccessKind) -> bool) -> T {
+    AccessKind::ALL
+        .into_iter()
+        .filter(|&k| filter(k))
+        .map(|k| counts[k as usize])
+        .sum()
+}
+
+impl CacheStats {
+    fn new(
+        name: String,
+        core: Option<usize>,
+        counts: CacheCounts,
+        mshr: MshrStats,
+        policy: Option<serde_json::Value>,
+        prefetch: Option<PrefetchStats>,
+    ) -> Self {
+        let per_kilo = |misses: f64| misses * 1000f64 / counts.instructions;
+
+        let misses = count(&counts.misses, AccessKind::is_demand);
+        let hits = count(&counts.hits, AccessKind::is_demand);
+        let total_access = misses + hits;
+        let total_dead: f64 = counts.dead.iter().sum();
+        let total_live: f64 = counts.live.iter().sum();
+        let total_both = total_dead + total_live;
+
+        let efficiency_im = iter::zip(&counts.live, &counts.dead)
+            .map(|(&live, &dead)| {
+                let total = live + dead;
+                if total == 0f64 {
+                    0f64
+                } else {
+                    live / total
+                }
+            })
+            .collect();
+
+        let by_kind = AccessKind::ALL
+            .into_iter()
+            .map(|k| {
+                let stats = AccessStats {
+                    misses: counts.misses[k as usize].round() as u64,
+                    hits: counts.hits[k as usize].round() as u64,
+                    mpki: per_kilo(counts.misses[k as usize]),
+                };
+                (k.name(), stats)
+            })
+            .collect();
+
+        let writeback = AccessKind::Writeback as usize;
+        CacheStats {
+            name,
+            core,
+            misses: misses.round() as u64,
+            hits: hits.round() as u64,
+            miss_rate: misses / total_access,
+            mpki: per_kilo(misses),
+            instr_mpki: per_kilo(counts.misses[AccessKind::Fetch as usize]),
+            data_mpki: per_kilo(count(&counts.misses, AccessKind::is_data)),
+            by_kind,
+            reuse: total_access / counts.allocations,
+            lifetime: total_both / counts.allocations,
+            efficiency: total_live / total_both,
+            efficiency_im,
+            writebacks_issued: counts.writebacks_issued.round() as u64,
+            writebacks_received: (counts.misses[writeback] + counts.hits[writeback]).round() as u64,
+            back_invalidations_issued: counts.back_invalidations_issued.round() as u64,
+            back_invalidations_received: counts.back_invalidations_received.round() as u64,
+            amat: counts.demand_cycles / total_access,
+            mshr,
+            policy,
+            prefetch,
+            counts,
+        }
+    }
+
+    /// Regions' counts summed by weight. Policies' own stats are left out, as
+    /// they are not counts that can be summed.
+    pub fn weighted(regions: &[(f64, &CacheStats)]) -> Self {
+        let first = regions[0].1;
+        assert!(
+            regions.iter().all(|(_, s)| s.name == first.name),
+            "Regions should have the same caches"
+        );
+        let sum = |count: fn(&CacheCounts) -> f64| simpoint::sum(regions, |s| count(&s.counts));
+        let per_block = |durations: fn(&CacheCounts) -> &Vec<f64>| {
+            (0..durations(&first.counts).len())
+                .map(|idx| simpoint::sum(regions, |s| durations(&s.counts)[idx]))
+                .collect()
+        };
+        let counts = CacheCounts {
+            instructions: sum(|c| c.instructions),
+            misses: std::array::from_fn(|k| simpoint::sum(regions, |s| s.counts.misses[k])),
+            hits: std::array::from_fn(|k| simpoint::sum(regions, |s| s.counts.hits[k])),
+            demand_cycles: sum(|c| c.demand_cycles),
+            allocations: sum(|c| c.allocations),
+            live: per_block(|c| &c.live),
+            dead: per_block(|c| &c.dead),
+            writebacks_issued: sum(|c| c.writebacks_issued),
+            back_invalidations_issued: sum(|c| c.back_invalidations_issued),
+            back_invalidations_received: sum(|c| c.back_invalidations_received),
+        };
+        let mshrs: Vec<(f64, &MshrStats)> =
+            regions.iter().map(|&(scale, s)| (scale, &s.mshr)).collect();
+        let prefetch = first.prefetch.as_ref().map(|_| {
+            let prefetches: Vec<(f64, &PrefetchStats)> = regions
+                .iter()
+                .map(|&(scale, s)| (scale, s.prefetch.as_ref().unwrap()))
+                .collect();
+            PrefetchStats::weighted(&prefetches)
+        });
+        CacheStats::new(
+            first.name.clone(),
+            first.core,
+            counts,
+            MshrStats::weighted(&mshrs),
+            first
+                .policy
+                .as_ref()
+                .map(|_| serde_json::Value::from("not aggregatable")),
+            prefetch,
+        )
+    }
+}
+
+/// Everything about a cache level except its replacement policy
 #[derive(Debug)]
-pub struct Cache<S: MakeS, B: Default, R: Replace<S, B>> {
+pub struct CacheParams {
+    pub name: String,
+    /// The core this copy is private to, None if shared
+    pub core: Option<usize>,
+    pub block_size: usize,
+    pub n_sets: usize,
+    pub n_ways: usize,
+    pub write_allocate: bool,
+    pub inclusion: Inclusion,
+    /// Cycles to return a hit
+    pub latency: u64,
+    /// Outstanding misses before the cache stalls, None for no limit
+    pub mshrs: Option<usize>,
+    /// Name of the prefetcher feeding this cache
+    pub prefetch: Option<String>,
+}
+
+#[derive(Debug)]
+pub struct Cache<S: MakeS, B: Default, R: Policy<S, B>> {
     name: String,
+    core: Option<usize>,
     pub blocks: Vec<Block<B>>,
     pub set_data: Vec<S>,
     pub block_size: usize,
@@ -54,12 +320,34 @@ pub struct Cache<S: MakeS, B: Default, R: Replace<S, B>> {
     set_sec: BitSection,
     tag_sec: BitSection,
     pub repl: R,
-    hits: u64,
-    misses: u64,
+    pub write_allocate: bool,
+    pub inclusion: Inclusion,
+    pub latency: u64,
+    pub mshr: Mshr,
+    pub prefetcher: Option<Prefetcher>,
+    hits: [u64; AccessKind::ALL.len()],
+    misses: [u64; AccessKind::ALL.len()],
+    writebacks_issued: u64,
+    back_invalidations_issued: u64,
+    back_invalidations_received: u64,
+    /// Total cycles taken by demand accesses, including lower levels
+    demand_cycles: u64,
 }
 
-impl<S: MakeS, B: Default, R: Replace<S, B>> Cache<S, B, R> {
-    pub fn new(name: String, block_size: usize, n_sets: usize, n_ways: usize, repl: R) -> Self {
+impl<S: MakeS, B: Default, R: Policy<S, B>> Cache<S, B, R> {
+    pub fn new(params: CacheParams, repl: R) -> Self {
+        let CacheParams {
+            name,
+            core,
+            block_size,
+            n_sets,
+            n_ways,
+            write_allocate,
+            inclusion,
+            latency,
+            mshrs,
+            prefetch,
+        } = params;
         assert!(n_ways.is_power_of_two());
 
         assert!(block_size.is_power_of_two());
@@ -83,6 +371,7 @@ impl<S: MakeS, B: Default, R: Replace<S, B>> Cache<S, B, R> {
 
         Cache {
             name,
+            core,
             blocks: iter::repeat_with(|| Block::default())
                 .take(n_sets * n_ways)
                 .collect(),
@@ -94,25 +383,124 @@ impl<S: MakeS, B: Default, R: Replace<S, B>> Cache<S, B, R> {
             set_sec,
             tag_sec,
             repl,
-            hits: 0,
-            misses: 0,
+            write_allocate,
+            inclusion,
+            latency,
+            mshr: Mshr::new(mshrs),
+            prefetcher: prefetch.map(|name| Prefetcher::new(&name, block_size, n_sets * n_ways)),
+            hits: [0; AccessKind::ALL.len()],
+            misses: [0; AccessKind::ALL.len()],
+            writebacks_issued: 0,
+            back_invalidations_issued: 0,
+            back_invalidations_received: 0,
+            demand_cycles: 0,
+        }
+    }
+
+    pub fn allocates(&self, kind: AccessKind) -> bool {
+        match self.inclusion {
+            // Only lines leaving the levels above are placed here
+            Inclusion::Exclusive => matches!(kind, AccessKind::Writeback | AccessKind::Victim),
+            _ => !kind.is_write() || self.write_allocate,
         }
     }
 }
 
+impl<S: MakeS, B: Default, R: Replace<S, B>> Cache<S, B, R> {
+    /// Looks the line up, filling it on a miss, and tells the policy
+    pub fn lookup(&mut self, cpu: &Cpu, addr: Addr, kind: AccessKind) -> AccessResult {
+        let allocate = self.allocates(kind);
+        // Bypassing an inclusive cache would leave lines above it untracked
+        let may_bypass = self.inclusion != Inclusion::Inclusive;
+        let access = Access {
+            set: addr.set,
+            tag: addr.tag,
+            line: self.join_addr(addr.set, addr.tag),
+            kind,
+        };
+        let mut set = Set {
+            data: &mut self.set_data[addr.set],
+            blocks: &mut self.blocks[addr.set * self.n_ways..(addr.set + 1) * self.n_ways],
+        };
+        let repl = &mut self.repl;
+        repl.on_access(cpu, &access, &mut set);
+
+        if let Some(way) = set.blocks.iter().position(|b| b.valid && b.tag == addr.tag) {
+            set.blocks[way].read(cpu, kind);
+            repl.on_hit(cpu, &access, &mut set, way);
+            return AccessResult::Hit;
+        }
+        if !allocate {
+            return AccessResult::Miss(None);
+        }
+        if may_bypass && repl.bypass(cpu, &access, &mut set) {
+            return AccessResult::Bypass;
+        }
+
+        let (way, evicted) = match set.blocks.iter().position(|b| !b.valid) {
+            Some(way) => (way, None),
+            None => {
+                let way = repl.find_victim(cpu, &access, &mut set);
+                repl.on_evict(&mut set, way, Eviction::Replaced);
+                (way, Some(set.blocks[way].evict(cpu, addr.set)))
+            }
+        };
+        let block = &mut set.blocks[way];
+        block.apply(addr, kind);
+        block.alloc(cpu);
+        repl.on_fill(cpu, &access, &mut set, way);
+        AccessResult::Miss(evicted)
+    }
+
+    /// Removes the line if present, telling the policy
+    pub fn remove(&mut self, cpu: &Cpu, addr: Addr) -> Option<Evicted> {
+        let mut set = Set {
+            data: &mut self.set_data[addr.set],
+            blocks: &mut self.blocks[addr.set * self.n_ways..(addr.set + 1) * self.n_ways],
+        };
+        let way = set
+            .blocks
+            .iter()
+            .position(|b| b.valid && b.tag == addr.tag)?;
+        self.repl.on_evict(&mut set, way, Eviction::Invalidated);
+        let block = &mut set.blocks[way];
+        let evicted = block.evict(cpu, addr.set);
+        block.valid = false;
+        Some(evicted)
+    }
+}
+
 pub trait IsCache {
-    fn access(&mut self, cpu: &mut Cpu, addr: Addr) -> AccessResult;
+    fn access(&mut self, cpu: &mut Cpu, addr: Addr, kind: AccessKind) -> AccessResult;
+    fn invalidate(&mut self, cpu: &mut Cpu, addr: Addr) -> Option<Evicted>;
     fn split_addr(&self, addr: usize) -> Addr;
+    fn join_addr(&self, set: usize, tag: usize) -> usize;
+    fn block_size(&self) -> usize;
     fn get_set(&mut self, set: usize) -> Range<usize>;
-    fn hit(&mut self);
-    fn miss(&mut self);
+    fn allocates(&self, kind: AccessKind) -> bool;
+    fn core(&self) -> Option<usize>;
+    fn inclusion(&self) -> Inclusion;
+    fn latency(&self) -> u64;
+    fn mshr(&mut self) -> &mut Mshr;
+    fn prefetcher(&mut self) -> Option<&mut Prefetcher>;
+    fn add_demand_cycles(&mut self, cycles: u64);
+    fn hit(&mut self, kind: AccessKind);
+    fn miss(&mut self, kind: AccessKind);
+    fn writeback_issued(&mut self);
+    fn back_invalidations_issued(&mut self, count: u64);
+    fn back_invalidation_received(&mut self);
+    fn recording(&mut self) -> Option<Recording>;
     fn clear_stats(&mut self);
     fn make_stats(&self, cpu: &Cpu) -> CacheStats;
 }
 
-impl<S: MakeS, B: Default, R: Replace<S, B>> IsCache for Cache<S, B, R> {
-    fn access(&mut self, cpu: &mut Cpu, addr: Addr) -> AccessResult {
-        R::access(cpu, self, addr)
+impl<S: MakeS, B: Default, R: Policy<S, B>> IsCache for Cache<S, B, R> {
+    fn access(&mut self, cpu: &mut Cpu, addr: Addr, kind: AccessKind) -> AccessResult {
+        R::access(cpu, self, addr, kind)
+    }
+
+    fn invalidate(&mut self, cpu: &mut Cpu, addr: Addr) -> Option<Evicted> {
+        R::invalidate(cpu, self, addr)
     }
 
     fn split_addr(&self, addr: usize) -> Addr {
@@ -122,21 +510,82 @@ impl<S: MakeS, B: Default, R: Replace<S, B>> IsCache for Cache<S, B, R> {
         Addr { offset, set, tag }
     }
 
+    fn join_addr(&self, set: usize, tag: usize) -> usize {
+        self.set_sec.place(set) | self.tag_sec.place(tag)
+    }
+
+    fn block_size(&self) -> usize {
+        self.block_size
+    }
+
     fn get_set(&mut self, set: usize) -> Range<usize> {
         set * self.n_ways..(set + 1) * self.n_ways
     }
 
-    fn hit(&mut self) {
-        self.hits += 1;
+    fn allocates(&self, kind: AccessKind) -> bool {
+        Cache::allocates(self, kind)
+    }
+
+    fn core(&self) -> Option<usize> {
+        self.core
+    }
+
+    fn inclusion(&self) -> Inclusion {
+        self.inclusion
+    }
+
+    fn latency(&self) -> u64 {
+        self.latency
+    }
+
+    fn mshr(&mut self) -> &mut Mshr {
+        &mut self.mshr
+    }
+
+    fn prefetcher(&mut self) -> Option<&mut Prefetcher> {
+        self.prefetcher.as_mut()
+    }
+
+    fn add_demand_cycles(&mut self, cycles: u64) {
+        self.demand_cycles += cycles;
+    }
+
+    fn hit(&mut self, kind: AccessKind) {
+        self.hits[kind as usize] += 1;
+    }
+
+    fn miss(&mut self, kind: AccessKind) {
+        self.misses[kind as usize] += 1;
     }
 
-    fn miss(&mut self) {
-        self.misses += 1;
+    fn writeback_issued(&mut self) {
+        self.writebacks_issued += 1;
+    }
+
+    fn back_invalidations_issued(&mut self, count: u64) {
+        self.back_invalidations_issued += count;
+    }
+
+    fn back_invalidation_received(&mut self) {
+        self.back_invalidations_received += 1;
+    }
+
+    fn recording(&mut self) -> Option<Recording> {
+        self.repl.recording()
     }
 
     fn clear_stats(&mut self) {
-        self.misses = 0;
-        self.hits = 0;
+        self.misses = [0; AccessKind::ALL.len()];
+        self.hits = [0; AccessKind::ALL.len()];
+        self.writebacks_issued = 0;
+        self.back_invalidations_issued = 0;
+        self.back_invalidations_received = 0;
+        self.demand_cycles = 0;
+        self.mshr.clear_stats();
+        self.repl.clear_stats();
+        if let Some(prefetcher) = &mut self.prefetcher {
+            prefetcher.clear_stats();
+        }
         for block in &mut self.blocks {
             block.block_stats.live_dur = 0;
             block.block_stats.dead_dur = 0;
@@ -146,59 +595,52 @@ impl<S: MakeS, B: Default, R: Replace<S, B>> IsCache for Cache<S, B, R> {
                 0
             };
             block.block_stats.access_count = 0;
+            block.block_stats.prefetch_fills = 0;
+            block.block_stats.prefetch_useful = 0;
+            block.block_stats.prefetch_useless = 0;
         }
     }
 
     fn make_stats(&self, cpu: &Cpu) -> CacheStats {
-        let total_alloc: f64 = self
-            .blocks
-            .iter()
-            .map(|b| b.block_stats.alloc_count as f64)
-            .sum();
-        let total_dead: f64 = self
-            .blocks
-            .iter()
-            .map(|b| b.block_stats.dead_dur as f64)
-            .sum();
-        let total_live: f64 = self
-            .blocks
-            .iter()
-            .map(|b| b.block_stats.live_dur as f64)
-            .sum();
-        let total_both: f64 = total_dead + total_live;
-
-        let total_access = (self.misses + self.hits) as f64;
-
-        let miss_rate = self.misses as f64 / total_access;
-        let mpki = self.misses as f64 / cpu.instr_idx as f64;
-        let reuse = total_access / total_alloc;
-        let lifetime = total_both / total_alloc;
-        let efficiency = total_live / total_both;
-
-        let efficiency_im = self
-            .blocks
-            .iter()
-            .map(|b| {
-                let total = b.block_stats.live_dur + b.block_stats.dead_dur;
-                if total == 0 {
-                    0f64
-                } else {
-                    b.block_stats.live_dur as f64 / total as f64
-                }
-            })
-            .collect();
-
-        CacheStats {
-            name: self.name.clone(),
-            miss_rate,
-            mpki,
-            reuse,
-            lifetime,
-            efficiency,
-            efficiency_im,
-            misses: self.misses,
-            hits: self.hits,
-        }
+        let block_count = |stat: fn(&BlockStats) -> u64| -> u64 {
+            self.blocks.iter().map(|b| stat(&b.block_stats)).sum()
+        };
+        let counts = CacheCounts {
+            instructions: cpu.instrs() as f64,
+            misses: self.misses.map(|count| count as f64),
+            hits: self.hits.map(|count| count as f64),
+            demand_cycles: self.demand_cycles as f64,
+            allocations: block_count(|s| s.alloc_count) as f64,
+            live: self
+                .blocks
+                .iter()
+                .map(|b| b.block_stats.live_dur as f64)
+                .collect(),
+            dead: self
+                .blocks
+                .iter()
+                .map(|b| b.block_stats.dead_dur as f64)
+                .collect(),
+            writebacks_issued: self.writebacks_issued as f64,
+            back_invalidations_issued: self.back_invalidations_issued as f64,
+            back_invalidations_received: self.back_invalidations_received as f64,
+        };
+        let prefetch = self.prefetcher.as_ref().map(|p| {
+            p.make_stats(
+                block_count(|s| s.prefetch_fills),
+                block_count(|s| s.prefetch_useful),
+                block_count(|s| s.prefetch_useless),
+                count(&self.misses, AccessKind::is_demand),
+            )
+        });
+        CacheStats::new(
+            self.name.clone(),
+            self.core,
+            counts,
+            self.mshr.make_stats(cpu.cycles()),
+            self.repl.stats(),
+            prefetch,
+        )
     }
 }
 
@@ -208,11 +650,17 @@ pub struct BlockStats {
     dead_dur: u64,
     alloc_count: u64,
     access_count: u64,
+    prefetch_fills: u64,
+    prefetch_useful: u64,
+    prefetch_useless: u64,
 }
 
 #[derive(Debug, Default)]
 pub struct Block<B: Default> {
     pub valid: bool,
+    pub dirty: bool,
+    /// Filled by a prefetch and not yet used by a demand access
+    pub prefetched: bool,
     pub tag: usize,
 
     pub block_stats: BlockStats,
@@ -226,24 +674,42 @@ pub struct Block<B: Default> {
 }
 
 impl<B: Default> Block<B> {
-    pub fn apply(&mut self, addr: Addr) {
+    pub fn apply(&mut self, addr: Addr, kind: AccessKind) {
         self.valid = true;
+        self.dirty = kind.is_write();
+        self.prefetched = kind == AccessKind::Prefetch;
         self.tag = addr.tag;
+        if self.prefetched {
+            self.block_stats.prefetch_fills += 1;
+        }
     }
 
     pub fn alloc(&mut self, cpu: &Cpu) {
-        self.alloc_time = cpu.instr_idx;
-        self.access_time = cpu.instr_idx;
+        self.alloc_time = cpu.time;
+        self.access_time = cpu.time;
         self.block_stats.alloc_count += 1;
     }
 
-    pub fn read(&mut self, cpu: &Cpu) {
-        self.access_time = cpu.instr_idx;
+    pub fn read(&mut self, cpu: &Cpu, kind: AccessKind) {
+        self.access_time = cpu.time;
         self.block_stats.access_count += 1;
+        self.dirty |= kind.is_write();
+        if self.prefetched && kind.is_demand() {
+            self.prefetched = false;
+            self.block_stats.prefetch_useful += 1;
+        }
     }
 
-    pub fn evict(&mut self, cpu: &Cpu) {
+    pub fn evict(&mut self, cpu: &Cpu, set: usize) -> Evicted {
         self.block_stats.live_dur += self.access_time - self.alloc_time;
-        self.block_stats.dead_dur += cpu.instr_idx - self.access_time;
+        self.block_stats.dead_dur += cpu.time - self.access_time;
+        if self.prefetched {
+            self.block_stats.prefetch_useless += 1;
+        }
+        Evicted {
+            set,
+            tag: self.tag,
+            dirty: self.dirty,
+        }
     }
 }
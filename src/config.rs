@@ -1,53 +1,281 @@
 use serde::Deserialize;
 
 use crate::{
-    cache::{Cache, IsCache},
-    replace::{lru::Lru, lrudb::Lrudb, nmru::Nmru},
+    branch::{Branch, BranchConfig},
+    cache::{Cache, CacheParams, Inclusion, IsCache},
+    hierarchy::Hierarchy,
+    ooo::{Ooo, OooConfig},
+    replace::{
+        dip::{Dip, Insertion},
+        fifo::Fifo,
+        hawkeye::Hawkeye,
+        lfu::Lfu,
+        lru::Lru,
+        lrudb::Lrudb,
+        nmru::Nmru,
+        opt::{Opt, Recording},
+        plru::{BitPlru, TreePlru},
+        random::Random,
+        rrip::{Rrip, RripMode},
+        sdbp::Sdbp,
+        ship::{Ship, Signature},
+    },
 };
 
 #[derive(Deserialize)]
 struct CacheConfig {
     name: String,
+    /// Overrides the hierarchy-wide block size for this cache
+    block_size: Option<usize>,
     sets: usize,
     ways: usize,
     repl: String,
+    #[serde(default)]
+    repl_options: ReplOptions,
+    #[serde(default = "default_write_allocate")]
+    write_allocate: bool,
+    #[serde(default)]
+    inclusion: Inclusion,
+    /// Cycles to return a hit
+    #[serde(default = "default_latency")]
+    latency: u64,
+    /// Outstanding misses before the cache stalls, unlimited if not given
+    mshrs: Option<usize>,
+    /// Prefetcher feeding this cache: next_line, ip_stride or stream
+    prefetch: Option<String>,
+    /// The cache this one misses into, defaults to the following cache
+    next: Option<String>,
+    /// One copy serves every core, instead of one copy per core
+    #[serde(default)]
+    shared: bool,
+}
+
+/// Tuning for the replacement policies that take any
+#[derive(Deserialize, Default)]
+struct ReplOptions {
+    /// Width of the RRIP policies' re-reference prediction values
+    rrpv_bits: Option<u32>,
+    /// What SHiP builds signatures from: pc, memory or iseq
+    signature: Option<String>,
+    /// Log2 of SHiP's signature history counter table entries
+    shct_bits: Option<u32>,
+    /// How often BIP and DIP's bimodal side insert at MRU
+    bip_epsilon: Option<f64>,
+    /// Seed for random replacement
+    seed: Option<u64>,
+    /// Accesses to a set before LFU halves its counts, never if not given
+    lfu_aging: Option<u64>,
+    /// Whether SDBP skips filling lines predicted dead, on if not given
+    bypass: Option<bool>,
+}
+
+fn default_write_allocate() -> bool {
+    true
+}
+
+fn default_block_size() -> usize {
+    64
+}
+
+fn default_latency() -> u64 {
+    1
+}
+
+fn default_memory_latency() -> u64 {
+    200
 }
 
 #[derive(Deserialize)]
 pub struct Config {
+    #[serde(default = "default_block_size")]
     block_size: usize,
     caches: Vec<CacheConfig>,
+    /// The cache instruction fetches enter, defaults to the first cache
+    fetch: Option<String>,
+    /// The cache loads and stores enter, defaults to the first cache
+    data: Option<String>,
+    /// Cycles to return a line from memory
+    #[serde(default = "default_memory_latency")]
+    memory_latency: u64,
+    /// Runs an out-of-order core instead of the in-order one
+    ooo: Option<OooConfig>,
+    /// Branch predictors to evaluate over the trace
+    branch: Option<BranchConfig>,
 }
 
 impl Config {
-    pub fn to_caches(self) -> Vec<Box<dyn IsCache>> {
-        let block_size = self.block_size;
+    fn find_cache(&self, name: &str) -> usize {
         self.caches
-            .into_iter()
-            .map(|cc| match cc.repl.as_str() {
-                "nmru" => Box::new(Cache::new(
-                    cc.name,
-                    block_size,
-                    cc.sets,
-                    cc.ways,
-                    Nmru::new(),
-                )) as Box<dyn IsCache>,
-                "lru" => Box::new(Cache::new(
-                    cc.name,
-                    block_size,
-                    cc.sets,
-                    cc.ways,
-                    Lru::new(),
-                )) as Box<dyn IsCache>,
-                "lrudb" => Box::new(Cache::new(
-                    cc.name,
-                    block_size,
-                    cc.sets,
-                    cc.ways,
-                    Lrudb::new(),
-                )) as Box<dyn IsCache>,
-                _ => panic!("Unrecognized replacement policy: {}", &cc.repl),
-            })
-            .collect()
+            .iter()
+            .position(|cc| cc.name == name)
+            .unwrap_or_else(|| panic!("Unrecognized cache name: {}", name))
+    }
+
+    pub fn ooo(&self) -> Option<Ooo> {
+        self.ooo.clone().map(Ooo::new)
+    }
+
+    pub fn branch(&self) -> Option<Branch> {
+        self.branch.clone().map(Branch::new)
+    }
+
+    /// Whether a cache needs the future, and the trace has to run again
+    pub fn needs_future(&self) -> bool {
+        self.caches.iter().any(|cc| cc.repl == "opt")
+    }
+
+    /// Whether any cache prefetches
+    pub fn prefetches(&self) -> bool {
+        self.caches.iter().any(|cc| cc.prefetch.is_some())
+    }
+
+    /// Builds the caches for `n_cores` cores, each with its own copy of
+    /// every cache that is not shared. `recordings` holds what each cache
+    /// saw in the last pass, and is empty before one has run.
+    pub fn to_hierarchy(
+        &self,
+        n_cores: usize,
+        mut recordings: Vec<Option<Recording>>,
+    ) -> Hierarchy {
+        let mut recording = |idx: usize| recordings.get_mut(idx).and_then(Option::take);
+        // Index of each core's copy of every configured cache
+        let mut copies: Vec<Vec<usize>> = Vec::new();
+        let mut caches = Vec::new();
+        for cc in &self.caches {
+            if cc.shared {
+                copies.push(vec![caches.len(); n_cores]);
+                caches.push(self.make_cache(cc, None, recording(caches.len())));
+            } else {
+                copies.push((caches.len()..caches.len() + n_cores).collect());
+                for core in 0..n_cores {
+                    let seen = recording(caches.len());
+                    caches.push(self.make_cache(cc, Some(core), seen));
+                }
+            }
+        }
+
+        let mut next = vec![None; caches.len()];
+        for (idx, cc) in self.caches.iter().enumerate() {
+            let lower = match &cc.next {
+                Some(name) if name == "memory" => None,
+                Some(name) => {
+                    let lower = self.find_cache(name);
+                    assert!(
+                        lower > idx,
+                        "Cache {} must miss into a cache listed after it",
+                        cc.name
+                    );
+                    Some(lower)
+                }
+                None => Some(idx + 1).filter(|&lower| lower < self.caches.len()),
+            };
+            if let Some(lower) = lower {
+                assert!(
+                    !cc.shared || self.caches[lower].shared,
+                    "Shared cache {} cannot miss into a private cache",
+                    cc.name
+                );
+            }
+            for core in 0..n_cores {
+                next[copies[idx][core]] = lower.map(|lower| copies[lower][core]);
+            }
+        }
+
+        let entries = |name: &Option<String>| {
+            let idx = name.as_deref().map_or(0, |name| self.find_cache(name));
+            copies[idx].clone()
+        };
+
+        Hierarchy::new(
+            caches,
+            next,
+            entries(&self.fetch),
+            entries(&self.data),
+            self.memory_latency,
+        )
+    }
+
+    fn make_cache(
+        &self,
+        cc: &CacheConfig,
+        core: Option<usize>,
+        recording: Option<Recording>,
+    ) -> Box<dyn IsCache> {
+        let params = CacheParams {
+            name: cc.name.clone(),
+            core,
+            block_size: cc.block_size.unwrap_or(self.block_size),
+            n_sets: cc.sets,
+            n_ways: cc.ways,
+            write_allocate: cc.write_allocate,
+            inclusion: cc.inclusion,
+            latency: cc.latency,
+            mshrs: cc.mshrs,
+            prefetch: cc.prefetch.clone(),
+        };
+        match cc.repl.as_str() {
+            "nmru" => Box::new(Cache::new(params, Nmru::new())) as Box<dyn IsCache>,
+            "lru" => Box::new(Cache::new(params, Lru::new())) as Box<dyn IsCache>,
+            "lrudb" => Box::new(Cache::new(params, Lrudb::new())) as Box<dyn IsCache>,
+            // Runs the whole simulation once per pass, up to 8 times, and keeps
+            // 20 bytes per access to the cache while it does. With several cores
+            // or prefetchers the stream it sees can shift between passes, so its
+            // stats are the last pass's and need not be exact Belady.
+            "opt" => Box::new(Cache::new(params, Opt::new(recording))) as Box<dyn IsCache>,
+            "srrip" | "brrip" | "drrip" => {
+                let mode = match cc.repl.as_str() {
+                    "srrip" => RripMode::Static,
+                    "brrip" => RripMode::Bimodal,
+                    _ => RripMode::Dynamic,
+                };
+                let rrpv_bits = cc.repl_options.rrpv_bits.unwrap_or(2);
+                Box::new(Cache::new(params, Rrip::new(mode, rrpv_bits))) as Box<dyn IsCache>
+            }
+            "fifo" => Box::new(Cache::new(params, Fifo::new())) as Box<dyn IsCache>,
+            "random" => {
+                let random = Random::new(cc.repl_options.seed.unwrap_or(0));
+                Box::new(Cache::new(params, random)) as Box<dyn IsCache>
+            }
+            "lfu" => {
+                let lfu = Lfu::new(cc.repl_options.lfu_aging);
+                Box::new(Cache::new(params, lfu)) as Box<dyn IsCache>
+            }
+            "tree_plru" => Box::new(Cache::new(params, TreePlru::new())) as Box<dyn IsCache>,
+            "bit_plru" => Box::new(Cache::new(params, BitPlru::new())) as Box<dyn IsCache>,
+            "lip" | "bip" | "dip" => {
+                let insertion = match cc.repl.as_str() {
+                    "lip" => Insertion::Lru,
+                    "bip" => Insertion::Bimodal,
+                    _ => Insertion::Dynamic,
+                };
+                let epsilon = cc.repl_options.bip_epsilon.unwrap_or(1.0 / 32.0);
+                Box::new(Cache::new(params, Dip::new(insertion, epsilon))) as Box<dyn IsCache>
+            }
+            "hawkeye" => {
+                let hawkeye = Hawkeye::new(cc.sets, cc.ways);
+                Box::new(Cache::new(params, hawkeye)) as Box<dyn IsCache>
+            }
+            "sdbp" => {
+                let bypass = cc.repl_options.bypass.unwrap_or(true);
+                let sdbp = Sdbp::new(cc.sets, cc.ways, bypass);
+                Box::new(Cache::new(params, sdbp)) as Box<dyn IsCache>
+            }
+            "ship" => {
+                let options = &cc.repl_options;
+                let signature = match options.signature.as_deref().unwrap_or("pc") {
+                    "pc" => Signature::Pc,
+                    "memory" => Signature::Memory,
+                    "iseq" => Signature::InstrSequence,
+                    other => panic!("Unrecognized SHiP signature: {}", other),
+                };
+                let ship = Ship::new(
+                    signature,
+                    options.shct_bits.unwrap_or(14),
+                    options.rrpv_bits.unwrap_or(2),
+                );
+                Box::new(Cache::new(params, ship)) as Box<dyn IsCache>
+            }
+            _ => panic!("Unrecognized replacement policy: {}", &cc.repl),
+        }
     }
 }
@@ -1,14 +1,91 @@
-#[derive(Debug)]
+use serde::Serialize;
+
+use crate::simpoint;
+
+#[derive(Debug, Clone)]
 pub struct Cpu {
+    pub id: usize,
     pub ip: u64,
     pub instr_idx: u64,
+    pub cycle: u64,
+    /// One bit per recent instruction, set if it accessed memory
+    pub iseq: u64,
+    /// Instructions run by every core so far, the clock block lifetimes use
+    pub time: u64,
+    // Where the measured region starts
+    stats_instr: u64,
+    stats_cycle: u64,
+}
+
+#[derive(Serialize)]
+pub struct CpuStats {
+    instructions: u64,
+    cycles: u64,
+    ipc: f64,
 }
 
 impl Cpu {
-    pub fn new() -> Self {
+    pub fn new(id: usize) -> Self {
         Cpu {
+            id,
             ip: 0,
             instr_idx: 0,
+            cycle: 0,
+            iseq: 0,
+            time: 0,
+            stats_instr: 0,
+            stats_cycle: 0,
+        }
+    }
+
+    /// Instructions run since stats were last cleared
+    pub fn instrs(&self) -> u64 {
+        self.instr_idx - self.stats_instr
+    }
+
+    /// Cycles run since stats were last cleared
+    pub fn cycles(&self) -> u64 {
+        self.cycle - self.stats_cycle
+    }
+
+    /// All cores as one, for stats of the caches they share
+    pub fn total(cpus: &[Cpu]) -> Self {
+        let mut total = Cpu::new(0);
+        total.instr_idx = cpus.iter().map(Cpu::instrs).sum();
+        total.cycle = cpus.iter().map(Cpu::cycles).max().unwrap();
+        total
+    }
+
+    pub fn clear_stats(&mut self) {
+        self.stats_instr = self.instr_idx;
+        self.stats_cycle = self.cycle;
+    }
+
+    pub fn make_stats(&self) -> CpuStats {
+        CpuStats::new(self.instrs() as f64, self.cycles() as f64)
+    }
+}
+
+impl CpuStats {
+    /// Counts are fractional when they come from weighted regions
+    fn new(instructions: f64, cycles: f64) -> Self {
+        CpuStats {
+            instructions: instructions.round() as u64,
+            cycles: cycles.round() as u64,
+            ipc: instructions / cycles,
         }
     }
+
+    pub fn instructions(&self) -> u64 {
+        self.instructions
+    }
+
+    /// Regions' counts summed by weight, so the IPC is the inverse of their
+    /// weighted CPI
+    pub fn weighted(regions: &[(f64, &CpuStats)]) -> Self {
+        CpuStats::new(
+            simpoint::sum(regions, |s| s.instructions as f64),
+            simpoint::sum(regions, |s| s.cycles as f64),
+        )
+    }
 }
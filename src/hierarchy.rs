@@ -0,0 +1,287 @@
+use crate::{
+    cache::{AccessKind, CacheStats, Evicted, Inclusion, IsCache},
+    cpu::Cpu,
+    replace::{opt::Recording, AccessResult},
+};
+
+/// Whoever sent an access down
+#[derive(Clone, Copy)]
+struct Upper {
+    /// Their line size, or 1 for the core
+    size: usize,
+    /// Whether they fill the line, so an exclusive level hands it over
+    fills: bool,
+}
+
+const CORE: Upper = Upper {
+    size: 1,
+    fills: false,
+};
+
+pub struct Hierarchy {
+    pub caches: Vec<Box<dyn IsCache>>,
+    /// Index of the level each cache misses into, None for memory
+    next: Vec<Option<usize>>,
+    /// Every cache whose misses eventually reach each level
+    uppers: Vec<Vec<usize>>,
+    /// Per core, the cache its fetches and its loads and stores enter
+    fetch_entry: Vec<usize>,
+    data_entry: Vec<usize>,
+    memory_latency: u64,
+}
+
+impl Hierarchy {
+    pub fn new(
+        caches: Vec<Box<dyn IsCache>>,
+        next: Vec<Option<usize>>,
+        fetch_entry: Vec<usize>,
+        data_entry: Vec<usize>,
+        memory_latency: u64,
+    ) -> Self {
+        assert_eq!(caches.len(), next.len());
+        assert_eq!(fetch_entry.len(), data_entry.len());
+        let mut uppers = vec![Vec::new(); caches.len()];
+        for upper in 0..caches.len() {
+            let mut level = next[upper];
+            while let Some(lower) = level {
+                uppers[lower].push(upper);
+                level = next[lower];
+            }
+        }
+        for (cache, uppers) in caches.iter().zip(&uppers) {
+            assert!(
+                cache.inclusion() != Inclusion::Exclusive || !uppers.is_empty(),
+                "An exclusive cache must have a cache above it"
+            );
+        }
+
+        Hierarchy {
+            caches,
+            next,
+            uppers,
+            fetch_entry,
+            data_entry,
+            memory_latency,
+        }
+    }
+
+    fn entry(&self, cpu: &Cpu, kind: AccessKind) -> usize {
+        match kind {
+            AccessKind::Fetch => self.fetch_entry[cpu.id],
+            _ => self.data_entry[cpu.id],
+        }
+    }
+
+    /// Cycles for an access of this kind that hits in the core's first level
+    pub fn hit_latency(&self, cpu: &Cpu, kind: AccessKind) -> u64 {
+        self.caches[self.entry(cpu, kind)].latency()
+    }
+
+    /// Returns the cycle the access issued at `now` completes
+    pub fn access(&mut self, cpu: &mut Cpu, addr: usize, kind: AccessKind, now: u64) -> u64 {
+        self.access_level(cpu, self.entry(cpu, kind), addr, CORE, kind, now)
+    }
+
+    /// Sends the line of `upper`'s size at `addr` to a lower level, one access
+    /// per line there
+    fn access_lower(
+        &mut self,
+        cpu: &mut Cpu,
+        lower: Option<usize>,
+        addr: usize,
+        upper: Upper,
+        kind: AccessKind,
+        now: u64,
+    ) -> u64 {
+        let Some(lower) = lower else {
+            return now + self.memory_latency;
+        };
+        // Lines from a level with smaller blocks are fetched in parallel
+        split_region(addr, upper.size, self.caches[lower].block_size())
+            .map(|line| self.access_level(cpu, lower, line, upper, kind, now))
+            .max()
+            .unwrap()
+    }
+
+    fn access_level(
+        &mut self,
+        cpu: &mut Cpu,
+        level: usize,
+        addr: usize,
+        upper: Upper,
+        kind: AccessKind,
+        now: u64,
+    ) -> u64 {
+        let lower = self.next[level];
+        let cache = &mut self.caches[level];
+        let block_size = cache.block_size();
+        let own_line = Upper {
+            size: block_size,
+            fills: false,
+        };
+        let line = addr & !(block_size - 1);
+        let hit_ready = now + cache.latency();
+        let result = cache.access(cpu, cache.split_addr(addr), kind);
+        let hit = matches!(result, AccessResult::Hit);
+        let ready = match result {
+            AccessResult::Hit => {
+                // A hit on a line that is still being filled waits for the fill,
+                // while a prefetch for it has nothing left to do
+                let pending = match kind {
+                    AccessKind::Prefetch => None,
+                    _ => cache.mshr().merge(line, hit_ready),
+                };
+                match pending {
+                    Some(_) => cache.miss(kind),
+                    None => cache.hit(kind),
+                }
+                if cache.inclusion() == Inclusion::Exclusive && upper.fills {
+                    // The line moves up into the cache that asked for it
+                    let moved = cache.invalidate(cpu, cache.split_addr(addr)).unwrap();
+                    if moved.dirty {
+                        cache.writeback_issued();
+                        let wb = AccessKind::Writeback;
+                        self.access_lower(cpu, lower, line, own_line, wb, hit_ready);
+                    }
+                }
+                pending.unwrap_or(hit_ready)
+            }
+            miss => {
+                cache.miss(kind);
+                let (evicted, allocated) = match miss {
+                    AccessResult::Miss(evicted) => (evicted, cache.allocates(kind)),
+                    _ => (None, false),
+                };
+                if allocated {
+                    let evicted_line = evicted.as_ref().map(|e| cache.join_addr(e.set, e.tag));
+                    if let Some(prefetcher) = cache.prefetcher() {
+                        prefetcher.fill(line, kind, evicted_line);
+                    }
+                }
+                if let Some(evicted) = evicted {
+                    self.evict(cpu, level, evicted, now);
+                }
+                // Writes that allocated here are filled from below as reads, as
+                // is the rest of a line only partly covered by a victim from above
+                let lower_kind = match kind {
+                    AccessKind::Store if allocated => AccessKind::Rfo,
+                    AccessKind::Writeback | AccessKind::Victim if allocated => {
+                        if upper.size >= block_size {
+                            return hit_ready;
+                        }
+                        AccessKind::Rfo
+                    }
+                    AccessKind::Victim => return hit_ready,
+                    _ => kind,
+                };
+                if allocated {
+                    let start = self.caches[level].mshr().reserve(now);
+                    let issue = start + self.caches[level].latency();
+                    let filling = Upper {
+                        fills: true,
+                        ..own_line
+                    };
+                    let ready = self.access_lower(cpu, lower, line, filling, lower_kind, issue);
+                    self.caches[level].mshr().allocate(line, start, ready);
+                    ready
+                } else {
+                    // Passing through, the line goes to whoever sent it
+                    self.access_lower(cpu, lower, addr, upper, lower_kind, hit_ready)
+                }
+            }
+        };
+        if kind.is_demand() {
+            self.caches[level].add_demand_cycles(ready - now);
+            self.prefetch(cpu, level, line, hit, now);
+        }
+        ready
+    }
+
+    /// Lets the level's prefetcher see a demand access and fill what it asks for
+    fn prefetch(&mut self, cpu: &mut Cpu, level: usize, line: usize, hit: bool, now: u64) {
+        let cache = &mut self.caches[level];
+        let Some(prefetcher) = cache.prefetcher() else {
+            return;
+        };
+        for target in prefetcher.access(line, cpu.ip, hit) {
+            // Prefetches never wait for an MSHR, they are dropped instead
+            let cache = &mut self.caches[level];
+            if !cache.mshr().has_free(now) {
+                cache.prefetcher().unwrap().dropped();
+                continue;
+            }
+            self.access_level(cpu, level, target, CORE, AccessKind::Prefetch, now);
+        }
+    }
+
+    fn evict(&mut self, cpu: &mut Cpu, level: usize, evicted: Evicted, now: u64) {
+        let addr = self.caches[level].join_addr(evicted.set, evicted.tag);
+        let block_size = self.caches[level].block_size();
+        let own_line = Upper {
+            size: block_size,
+            fills: false,
+        };
+        let mut dirty = evicted.dirty;
+
+        if self.caches[level].inclusion() == Inclusion::Inclusive {
+            let mut invalidated = 0;
+            for &upper in &self.uppers[level] {
+                let upper_cache = &mut self.caches[upper];
+                for upper_line in split_region(addr, block_size, upper_cache.block_size()) {
+                    let upper_addr = upper_cache.split_addr(upper_line);
+                    if let Some(copy) = upper_cache.invalidate(cpu, upper_addr) {
+                        upper_cache.back_invalidation_received();
+                        invalidated += 1;
+                        // Dirty data above leaves along with this line
+                        dirty |= copy.dirty;
+                    }
+                }
+            }
+            self.caches[level].back_invalidations_issued(invalidated);
+        }
+
+        let lower = self.next[level];
+        if dirty {
+            self.caches[level].writeback_issued();
+            self.access_lower(cpu, lower, addr, own_line, AccessKind::Writeback, now);
+        } else if lower.is_some_and(|l| self.caches[l].inclusion() == Inclusion::Exclusive) {
+            self.access_lower(cpu, lower, addr, own_line, AccessKind::Victim, now);
+        }
+    }
+
+    pub fn clear_stats(&mut self) {
+        self.caches.iter_mut().for_each(|c| c.clear_stats());
+    }
+
+    /// What each cache that needs the future saw this pass
+    pub fn recordings(&mut self) -> Vec<Option<Recording>> {
+        self.caches.iter_mut().map(|c| c.recording()).collect()
+    }
+
+    /// Stats for the caches private to this core
+    pub fn core_stats(&self, cpu: &Cpu) -> Vec<CacheStats> {
+        self.caches
+            .iter()
+            .filter(|c| c.core() == Some(cpu.id))
+            .map(|c| c.make_stats(cpu))
+            .collect()
+    }
+
+    /// Stats for the caches every core shares
+    pub fn shared_stats(&self, cpus: &[Cpu]) -> Vec<CacheStats> {
+        let total = Cpu::total(cpus);
+        self.caches
+            .iter()
+            .filter(|c| c.core().is_none())
+            .map(|c| c.make_stats(&total))
+            .collect()
+    }
+}
+
+/// The start of every `block_size` line overlapping the `size` bytes around `addr`
+fn split_region(addr: usize, size: usize, block_size: usize) -> impl Iterator<Item = usize> {
+    let size = size.max(1);
+    let base = addr & !(size - 1);
+    let start = base & !(block_size - 1);
+    (start..base + size).step_by(block_size)
+}
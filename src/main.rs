@@ -1,20 +1,194 @@
 #![feature(read_buf)]
 #![feature(get_many_mut)]
 
+mod branch;
 mod cache;
 mod config;
 mod cpu;
+mod hierarchy;
+mod mshr;
+mod ooo;
+mod prefetch;
 mod replace;
+mod simpoint;
 mod trace;
 
-use std::fs;
+use std::{fs, path::PathBuf};
 
-use cache::IsCache;
-use cpu::Cpu;
-use trace::{Instr, Trace};
+use branch::{Branch, BranchStats};
+use cache::{AccessKind, CacheStats};
+use cpu::{Cpu, CpuStats};
+use hierarchy::Hierarchy;
+use ooo::Ooo;
+use simpoint::{Region, RegionState};
+use trace::{BadRecord, Chunk, Instr, Trace, TraceEnd};
+
+use serde::Serialize;
 
 use crate::config::Config;
 
+#[derive(Serialize)]
+struct CoreStats {
+    #[serde(flatten)]
+    cpu: CpuStats,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    branch: Option<BranchStats>,
+    /// Times the trace ran out and started over
+    trace_wraps: u64,
+    /// Whether the trace ran out before the core ran all its instructions
+    trace_ended: bool,
+}
+
+#[derive(Serialize)]
+struct Stats {
+    cores: Vec<CoreStats>,
+    caches: Vec<CacheStats>,
+}
+
+impl CoreStats {
+    fn weighted(regions: &[(f64, &CoreStats)]) -> Self {
+        let cpus: Vec<(f64, &CpuStats)> =
+            regions.iter().map(|&(scale, s)| (scale, &s.cpu)).collect();
+        let branch = regions[0].1.branch.as_ref().map(|_| {
+            let branches: Vec<(f64, &BranchStats)> = regions
+                .iter()
+                .map(|&(scale, s)| (scale, s.branch.as_ref().unwrap()))
+                .collect();
+            BranchStats::weighted(&branches)
+        });
+        CoreStats {
+            cpu: CpuStats::weighted(&cpus),
+            branch,
+            trace_wraps: simpoint::sum(regions, |s| s.trace_wraps as f64).round() as u64,
+            trace_ended: regions.iter().any(|(_, s)| s.trace_ended),
+        }
+    }
+}
+
+impl Stats {
+    /// Each region's counts scaled and summed, with every rate worked out
+    /// from the sums as for a single run
+    fn weighted(regions: &[(f64, &Stats)]) -> Self {
+        let first = regions[0].1;
+        let cores = (0..first.cores.len())
+            .map(|idx| {
+                let cores: Vec<(f64, &CoreStats)> = regions
+                    .iter()
+                    .map(|&(scale, s)| (scale, &s.cores[idx]))
+                    .collect();
+                CoreStats::weighted(&cores)
+            })
+            .collect();
+        let caches = (0..first.caches.len())
+            .map(|idx| {
+                let caches: Vec<(f64, &CacheStats)> = regions
+                    .iter()
+                    .map(|&(scale, s)| (scale, &s.caches[idx]))
+                    .collect();
+                CacheStats::weighted(&caches)
+            })
+            .collect();
+        Stats { cores, caches }
+    }
+}
+
+#[derive(Serialize)]
+struct RegionStats {
+    start: u64,
+    weight: f64,
+    #[serde(flatten)]
+    stats: Stats,
+}
+
+/// Stats for each SimPoint region, and for the whole trace as they estimate it
+#[derive(Serialize)]
+struct SimPointStats {
+    regions: Vec<RegionStats>,
+    weighted: Stats,
+}
+
+/// A trace running on its own core
+struct Core {
+    cpu: Cpu,
+    ooo: Option<Ooo>,
+    branch: Option<Branch>,
+    trace: Trace,
+    instrs: Vec<Instr>,
+    next_instr: usize,
+    wraps: u64,
+    ended: bool,
+    bad_record: BadRecord,
+    /// Whether the trace stopped at a bad record rather than at its end
+    stopped: bool,
+    /// Taken once the core has run its measured instructions, along with its
+    /// private caches
+    stats: Option<(CoreStats, Vec<CacheStats>)>,
+}
+
+impl Core {
+    /// Runs the next instruction, returning false if the trace has ended
+    fn operate(&mut self, hierarchy: &mut Hierarchy) -> bool {
+        while self.next_instr == self.instrs.len() {
+            match self.trace.rec.recv() {
+                Ok(Chunk::Instrs(instrs)) => {
+                    self.instrs = instrs;
+                    self.next_instr = 0;
+                }
+                Ok(Chunk::Wrapped) => self.wraps += 1,
+                Ok(Chunk::SkipFailed(error)) => panic!(
+                    "Trace {}: --skip ran past its last good record: {}",
+                    self.trace.path.display(),
+                    error
+                ),
+                Ok(Chunk::Error(error)) => {
+                    let path = self.trace.path.display();
+                    match self.bad_record {
+                        BadRecord::Error => panic!("Trace {}: {}", path, error),
+                        BadRecord::Skip => {
+                            println!("Skipping the rest of trace {}: {}", path, error)
+                        }
+                        BadRecord::Stop => {
+                            println!("Stopping trace {}: {}", path, error);
+                            self.stopped = true;
+                        }
+                    }
+                }
+                Err(_) => return false,
+            }
+        }
+        let instr = &self.instrs[self.next_instr];
+        self.next_instr += 1;
+
+        if let Some(branch) = &mut self.branch {
+            branch.observe(instr);
+        }
+        let accesses_memory = instr.data_addresses().next().is_some();
+        self.cpu.iseq = (self.cpu.iseq << 1) | accesses_memory as u64;
+        match &mut self.ooo {
+            Some(ooo) => ooo.operate(&mut self.cpu, hierarchy, instr),
+            None => in_order(&mut self.cpu, hierarchy, instr),
+        }
+        true
+    }
+
+    fn clear_stats(&mut self) {
+        self.cpu.clear_stats();
+        if let Some(branch) = &mut self.branch {
+            branch.clear_stats();
+        }
+    }
+
+    fn make_stats(&self, hierarchy: &Hierarchy) -> (CoreStats, Vec<CacheStats>) {
+        let stats = CoreStats {
+            cpu: self.cpu.make_stats(),
+            branch: self.branch.as_ref().map(|b| b.make_stats(&self.cpu)),
+            trace_wraps: self.wraps,
+            trace_ended: self.ended,
+        };
+        (stats, hierarchy.core_stats(&self.cpu))
+    }
+}
+
 fn main() {
     let mut args = pico_args::Arguments::from_env();
     let n_warm: u64 = args
@@ -25,6 +199,11 @@ fn main() {
         .opt_value_from_str("-i")
         .expect("-i should be an integer")
         .unwrap_or(100_000_000);
+    // Records dropped from the start of every trace before any cache sees them
+    let n_skip: u64 = args
+        .opt_value_from_str("--skip")
+        .expect("--skip should be an integer")
+        .unwrap_or(0);
     let heartbeat_int: u64 = args
         .opt_value_from_str("-h")
         .expect("-h should be an integer")
@@ -41,19 +220,15 @@ fn main() {
         fs::read_to_string(config_path).expect("Could not find config file")
     };
     let config: Config = serde_json::from_str(&config_str).unwrap();
-    let mut caches = config.to_caches();
-    let mut cpu = Cpu::new();
 
     let stats_path: String = args
         .opt_value_from_str("--json")
         .unwrap()
         .expect("Must provide output path with --json");
-    let mut next_heartbeat = heartbeat_int;
 
-    let trace_path: String = args
-        .opt_value_from_str("-t")
-        .unwrap()
-        .expect("Must provide a trace with -t");
+    // Each trace runs on its own core
+    let trace_paths: Vec<String> = args.values_from_str("-t").unwrap();
+    assert!(!trace_paths.is_empty(), "Must provide a trace with -t");
     let inst_per_block: usize = args
         .opt_value_from_str("--buffer-size")
         .expect("--buffer-size must be an integer")
@@ -62,58 +237,330 @@ fn main() {
         .opt_value_from_str("--queue-size")
         .expect("--queue-size must be an integer")
         .unwrap_or(32);
+    // What a core does when its trace runs out: stop, wrap or error
+    let trace_end: TraceEnd = args
+        .opt_value_from_str("--trace-end")
+        .unwrap()
+        .unwrap_or(TraceEnd::Wrap);
+    // What a core does with a cut off or undecodable record: error, skip or stop
+    let bad_record: BadRecord = args
+        .opt_value_from_str("--bad-record")
+        .unwrap()
+        .unwrap_or(BadRecord::Error);
 
-    let trace = Trace::read(trace_path.into(), inst_per_block, blocks_per_queue).unwrap();
+    // Regions of the trace to run instead of its start, each warmed by -w
+    // instructions and measured for -i
+    let simpoints: Option<PathBuf> = args.opt_value_from_str("--simpoints").unwrap();
+    // Whether each region starts from empty caches or the last region's: fresh or carry
+    let region_state: RegionState = args
+        .opt_value_from_str("--regions")
+        .unwrap()
+        .unwrap_or(RegionState::Fresh);
 
-    let mut warmup = n_warm > 0;
-    let mut goal = if warmup { n_warm } else { n_instr };
+    let run = Run {
+        n_skip,
+        n_warm,
+        n_instr,
+        heartbeat_int,
+        trace_paths,
+        trace_end,
+        bad_record,
+        inst_per_block,
+        blocks_per_queue,
+    };
 
-    loop {
-        let instr_block = trace.rec.recv().unwrap();
-        operate(&mut cpu, &mut caches, &instr_block);
-        if heartbeat_int != 0 && cpu.instr_idx > next_heartbeat {
-            println!("Instr: {}", cpu.instr_idx);
-            while next_heartbeat < cpu.instr_idx {
-                next_heartbeat += heartbeat_int;
-            }
+    match simpoints {
+        Some(path) => {
+            let regions = simpoint::read(&path);
+            let stats = simulate_regions(&config, &run, &regions, region_state);
+            write_stats(&stats_path, &stats);
         }
+        None => write_stats(&stats_path, &simulate_fresh(&config, &run)),
+    }
+}
 
-        if cpu.instr_idx > goal {
-            if warmup {
-                caches.iter_mut().for_each(|c| c.clear_stats());
-                goal = cpu.instr_idx + n_instr;
-                warmup = false;
-                println!("Finished Warmup!")
-            } else {
-                break;
-            }
+/// Only written once the run has finished, so a run that fails leaves no file
+fn write_stats(path: &str, stats: &impl Serialize) {
+    let stats_file = fs::File::create(path).expect("Cannot open output file");
+    serde_json::to_writer_pretty(stats_file, stats).unwrap();
+}
+
+/// Runs from empty caches, over again for OPT until what it replays settles
+fn simulate_fresh(config: &Config, run: &Run) -> Stats {
+    let n_cores = run.trace_paths.len();
+    if config.needs_future() && (n_cores > 1 || config.prefetches()) {
+        println!(
+            "OPT's stats come from its last pass, and with several cores or prefetchers \
+            are not exact Belady"
+        );
+    }
+    let mut hierarchy = config.to_hierarchy(n_cores, Vec::new());
+    let (mut stats, _) = simulate(config, &mut hierarchy, run, new_cpus(n_cores));
+
+    // OPT caches replay what they saw in the pass before, until they all do
+    // or a pass gets none of them further into its replay than the one before
+    let mut passes = 1;
+    let mut matched = Vec::new();
+    while config.needs_future() {
+        let recordings = hierarchy.recordings();
+        if recordings.iter().flatten().all(|r| r.replayed) {
+            break;
+        }
+        let now_matched: Vec<usize> = recordings.iter().flatten().map(|r| r.matched).collect();
+        let progressed =
+            matched.is_empty() || now_matched.iter().zip(&matched).any(|(now, m)| now > m);
+        if passes == MAX_PASSES || !progressed {
+            println!(
+                "OPT's access stream did not settle after {} passes, reporting the last. \
+                Its own evictions change it when it is inclusive, as do cycle timings \
+                with several cores or prefetchers dropping prefetches on full MSHRs",
+                passes
+            );
+            break;
         }
+        matched = now_matched;
+        println!("Replaying for OPT");
+        hierarchy = config.to_hierarchy(n_cores, recordings);
+        (stats, _) = simulate(config, &mut hierarchy, run, new_cpus(n_cores));
+        passes += 1;
     }
-    println!("Ran {} instructions", cpu.instr_idx);
+    stats
+}
+
+/// Runs each region on its own, warming up on the instructions just before it
+fn simulate_regions(
+    config: &Config,
+    run: &Run,
+    regions: &[Region],
+    state: RegionState,
+) -> SimPointStats {
+    assert_eq!(run.n_skip, 0, "--skip cannot be used with --simpoints");
+    assert_eq!(
+        run.trace_paths.len(),
+        1,
+        "SimPoint regions are offsets into a single trace"
+    );
+    assert!(
+        state == RegionState::Fresh || !config.needs_future(),
+        "OPT replays each run from empty caches, so regions cannot carry state"
+    );
 
-    let stats = caches
+    let n_cores = run.trace_paths.len();
+    let mut hierarchy = config.to_hierarchy(n_cores, Vec::new());
+    let mut cpus = new_cpus(n_cores);
+    let mut region_stats = Vec::new();
+    for region in regions {
+        println!(
+            "Region at {} with weight {:.4}",
+            region.start, region.weight
+        );
+        let n_warm = region.warmup.unwrap_or(run.n_warm);
+        let n_skip = region.start.saturating_sub(n_warm);
+        let region_run = Run {
+            n_skip,
+            n_warm: region.start - n_skip,
+            n_instr: region.length.unwrap_or(run.n_instr),
+            ..run.clone()
+        };
+        let stats = match state {
+            RegionState::Fresh => simulate_fresh(config, &region_run),
+            RegionState::Carry => {
+                // Clocks carry on too, as the caches remember when lines were
+                // last touched and when misses in flight return
+                hierarchy.clear_stats();
+                cpus.iter_mut().for_each(Cpu::clear_stats);
+                let stats;
+                (stats, cpus) = simulate(config, &mut hierarchy, &region_run, cpus);
+                stats
+            }
+        };
+        region_stats.push(RegionStats {
+            start: region.start,
+            weight: region.weight,
+            stats,
+        });
+    }
+
+    // A region stands for its weight's share of the trace's instructions, so
+    // its counts are scaled per instruction it ran, and then to the regions'
+    // mean length. Rates then come out as SimPoint's weighted per-instruction
+    // estimates, such as IPC as the inverse of the weighted CPI.
+    let instructions = |r: &RegionStats| r.stats.cores[0].cpu.instructions() as f64;
+    let length: f64 = region_stats
         .iter()
-        .map(|c| c.make_stats(&cpu))
-        .collect::<Vec<_>>();
-
-    let stats_file = fs::File::create(stats_path).expect("Cannot open output file");
-    serde_json::to_writer_pretty(stats_file, &stats).unwrap();
-}
-
-fn operate(cpu: &mut Cpu, caches: &mut Vec<Box<dyn IsCache>>, instrs: &Vec<Instr>) {
-    for instr in instrs {
-        cpu.ip = instr.ip;
-        for addr in instr.addresses() {
-            for cache in caches.iter_mut() {
-                match cache.access(cpu, cache.split_addr(addr)) {
-                    replace::AccessResult::Hit => {
-                        cache.hit();
-                        break;
+        .map(|r| r.weight * instructions(r))
+        .sum();
+    let scaled: Vec<(f64, &Stats)> = region_stats
+        .iter()
+        .map(|r| {
+            assert!(
+                instructions(r) > 0.0,
+                "Region at {} ran no instructions",
+                r.start
+            );
+            (r.weight * length / instructions(r), &r.stats)
+        })
+        .collect();
+    let weighted = Stats::weighted(&scaled);
+    SimPointStats {
+        regions: region_stats,
+        weighted,
+    }
+}
+
+/// Enough for a chain of OPT caches, each settling a pass after the one above
+const MAX_PASSES: usize = 8;
+
+/// What to run, from the command line
+#[derive(Clone)]
+struct Run {
+    n_skip: u64,
+    n_warm: u64,
+    n_instr: u64,
+    heartbeat_int: u64,
+    trace_paths: Vec<String>,
+    trace_end: TraceEnd,
+    bad_record: BadRecord,
+    inst_per_block: usize,
+    blocks_per_queue: usize,
+}
+
+fn new_cpus(n_cores: usize) -> Vec<Cpu> {
+    (0..n_cores).map(Cpu::new).collect()
+}
+
+/// Runs a trace on each of `cpus`, returning them as they finished
+fn simulate(
+    config: &Config,
+    hierarchy: &mut Hierarchy,
+    run: &Run,
+    cpus: Vec<Cpu>,
+) -> (Stats, Vec<Cpu>) {
+    let start_time = cpus.iter().map(|cpu| cpu.time).max().unwrap();
+    let mut cores: Vec<Core> = cpus
+        .into_iter()
+        .zip(&run.trace_paths)
+        .map(|(cpu, path)| Core {
+            cpu,
+            ooo: config.ooo(),
+            branch: config.branch(),
+            trace: Trace::read(
+                path.into(),
+                run.n_skip,
+                run.trace_end,
+                run.bad_record,
+                run.inst_per_block,
+                run.blocks_per_queue,
+            )
+            .unwrap_or_else(|err| panic!("Could not read trace {}: {}", path, err)),
+            instrs: Vec::new(),
+            next_instr: 0,
+            wraps: 0,
+            ended: false,
+            bad_record: run.bad_record,
+            stopped: false,
+            stats: None,
+        })
+        .collect();
+
+    let mut warmup = run.n_warm > 0;
+    let mut time = start_time;
+    let mut next_heartbeat = time + run.heartbeat_int;
+
+    // Cores that finish early keep running so the shared caches stay contended,
+    // until their traces end
+    while cores.iter().any(|core| core.stats.is_none()) {
+        // The core furthest behind goes next, so shared caches see accesses in
+        // roughly cycle order
+        let id = (0..cores.len())
+            .filter(|&id| !cores[id].ended)
+            .min_by_key(|&id| cores[id].cpu.cycle)
+            .unwrap();
+        let core = &mut cores[id];
+        core.cpu.time = time;
+        if !core.operate(hierarchy) {
+            if !core.stopped {
+                let path = core.trace.path.display();
+                match run.trace_end {
+                    // A wrapping trace only ends on a pass with no good records, or
+                    // when it cannot be reopened
+                    TraceEnd::Wrap if core.wraps == 0 => {
+                        panic!("Trace {} has no good records to run", path)
                     }
-                    replace::AccessResult::Miss => cache.miss(),
+                    TraceEnd::Wrap => panic!(
+                        "Trace {} had no good records left after wrapping {} times, \
+                        at {} instructions",
+                        path, core.wraps, core.cpu.instr_idx
+                    ),
+                    TraceEnd::Error => panic!(
+                        "Trace {} ended after {} instructions",
+                        path, core.cpu.instr_idx
+                    ),
+                    TraceEnd::Stop => {}
                 }
+                println!(
+                    "Trace {} ended after {} instructions",
+                    path, core.cpu.instr_idx
+                );
+            }
+            core.ended = true;
+            if core.stats.is_none() {
+                core.stats = Some(core.make_stats(hierarchy));
+            }
+            continue;
+        }
+        time += 1;
+
+        if warmup {
+            let warm = |core: &Core| core.ended || core.cpu.instrs() >= run.n_warm;
+            if cores.iter().all(warm) {
+                hierarchy.clear_stats();
+                cores.iter_mut().for_each(Core::clear_stats);
+                warmup = false;
+                println!("Finished Warmup!")
             }
+        } else if core.stats.is_none() && core.cpu.instrs() >= run.n_instr {
+            core.stats = Some(core.make_stats(hierarchy));
+        }
+
+        if run.heartbeat_int != 0 && time > next_heartbeat {
+            println!("Instr: {}", time);
+            next_heartbeat += run.heartbeat_int;
+        }
+    }
+    println!("Ran {} instructions", time - start_time);
+
+    let mut cpus: Vec<Cpu> = cores.iter().map(|core| core.cpu.clone()).collect();
+    let mut stats = Stats {
+        cores: Vec::new(),
+        caches: Vec::new(),
+    };
+    for core in cores {
+        let (core_stats, caches) = core.stats.unwrap();
+        stats.cores.push(core_stats);
+        stats.caches.extend(caches);
+    }
+    stats.caches.extend(hierarchy.shared_stats(&cpus));
+    cpus.iter_mut().for_each(|cpu| cpu.time = time);
+    (stats, cpus)
+}
+
+/// A pipelined in-order core: each instruction takes a cycle, plus a stall
+/// for the fetch and the slowest load when they miss the first level.
+/// Stores drain in the background without stalling.
+fn in_order(cpu: &mut Cpu, hierarchy: &mut Hierarchy, instr: &Instr) {
+    cpu.ip = instr.ip;
+    let fetch_ready = hierarchy.access(cpu, instr.ip as usize, AccessKind::Fetch, cpu.cycle);
+    let fetch_stall = fetch_ready - cpu.cycle - hierarchy.hit_latency(cpu, AccessKind::Fetch);
+
+    let issue = cpu.cycle + fetch_stall;
+    let mut load_stall = 0;
+    for (addr, kind) in instr.data_addresses() {
+        let ready = hierarchy.access(cpu, addr, kind, issue);
+        if kind == AccessKind::Load {
+            load_stall = load_stall.max(ready - issue - hierarchy.hit_latency(cpu, kind));
         }
-        cpu.instr_idx += 1;
     }
+    cpu.cycle += 1 + fetch_stall + load_stall;
+    cpu.instr_idx += 1;
 }
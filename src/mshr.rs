@@ -0,0 +1,155 @@
+use serde::Serialize;
+
+use crate::simpoint;
+
+/// Miss status holding registers, tracking the lines a cache is waiting on
+#[derive(Debug)]
+pub struct Mshr {
+    capacity: Option<usize>,
+    /// Line address and the cycle its fill completes
+    in_flight: Vec<(usize, u64)>,
+    allocations: u64,
+    merges: u64,
+    full_stalls: u64,
+    stall_cycles: u64,
+    busy_cycles: u64,
+    peak: usize,
+}
+
+#[derive(Serialize)]
+pub struct MshrStats {
+    allocations: u64,
+    merges: u64,
+    full_stalls: u64,
+    stall_cycles: u64,
+    /// Average entries in use over the measured cycles
+    occupancy: f64,
+    peak_occupancy: usize,
+    #[serde(skip)]
+    busy_cycles: u64,
+    #[serde(skip)]
+    cycles: u64,
+}
+
+impl Mshr {
+    /// A capacity of None never stalls
+    pub fn new(capacity: Option<usize>) -> Self {
+        assert!(capacity != Some(0), "A cache needs at least one MSHR");
+        Mshr {
+            capacity,
+            in_flight: Vec::new(),
+            allocations: 0,
+            merges: 0,
+            full_stalls: 0,
+            stall_cycles: 0,
+            busy_cycles: 0,
+            peak: 0,
+        }
+    }
+
+    /// If the line is still being filled after a hit would have returned, merge
+    /// into its entry and return when it lands
+    pub fn merge(&mut self, line: usize, hit_ready: u64) -> Option<u64> {
+        let ready = self
+            .in_flight
+            .iter()
+            .find(|&&(l, ready)| l == line && ready > hit_ready)
+            .map(|&(_, ready)| ready)?;
+        self.merges += 1;
+        Some(ready)
+    }
+
+    /// Whether a miss at `now` would get an entry without waiting
+    pub fn has_free(&mut self, now: u64) -> bool {
+        self.in_flight.retain(|&(_, ready)| ready > now);
+        self.capacity
+            .is_none_or(|capacity| self.in_flight.len() < capacity)
+    }
+
+    /// Waits for a free entry, returning the cycle the miss can be sent below
+    pub fn reserve(&mut self, now: u64) -> u64 {
+        self.in_flight.retain(|&(_, ready)| ready > now);
+        let Some(capacity) = self.capacity else {
+            return now;
+        };
+        if self.in_flight.len() < capacity {
+            return now;
+        }
+        let free_at = self
+            .in_flight
+            .iter()
+            .map(|&(_, ready)| ready)
+            .min()
+            .unwrap();
+        self.in_flight.retain(|&(_, ready)| ready > free_at);
+        self.full_stalls += 1;
+        self.stall_cycles += free_at - now;
+        free_at
+    }
+
+    pub fn allocate(&mut self, line: usize, start: u64, ready: u64) {
+        self.in_flight.push((line, ready));
+        self.allocations += 1;
+        self.busy_cycles += ready - start;
+        self.peak = self.peak.max(self.in_flight.len());
+    }
+
+    pub fn clear_stats(&mut self) {
+        self.allocations = 0;
+        self.merges = 0;
+        self.full_stalls = 0;
+        self.stall_cycles = 0;
+        self.busy_cycles = 0;
+        self.peak = 0;
+    }
+
+    pub fn make_stats(&self, cycles: u64) -> MshrStats {
+        MshrStats::new(
+            [
+                self.allocations,
+                self.merges,
+                self.full_stalls,
+                self.stall_cycles,
+                self.busy_cycles,
+                cycles,
+            ]
+            .map(|count| count as f64),
+            self.peak,
+        )
+    }
+}
+
+impl MshrStats {
+    /// Counts are fractional when they come from weighted regions
+    fn new(counts: [f64; 6], peak_occupancy: usize) -> Self {
+        let [allocations, merges, full_stalls, stall_cycles, busy_cycles, cycles] = counts;
+        MshrStats {
+            allocations: allocations.round() as u64,
+            merges: merges.round() as u64,
+            full_stalls: full_stalls.round() as u64,
+            stall_cycles: stall_cycles.round() as u64,
+            occupancy: busy_cycles / cycles,
+            peak_occupancy,
+            busy_cycles: busy_cycles.round() as u64,
+            cycles: cycles.round() as u64,
+        }
+    }
+
+    /// Regions' counts summed by weight, and the highest peak of any
+    pub fn weighted(regions: &[(f64, &MshrStats)]) -> Self {
+        let counts = |s: &MshrStats| {
+            [
+                s.allocations,
+                s.merges,
+                s.full_stalls,
+                s.stall_cycles,
+                s.busy_cycles,
+                s.cycles,
+            ]
+        };
+        MshrStats::new(
+            std::array::from_fn(|idx| simpoint::sum(regions, |s| counts(s)[idx] as f64)),
+            regions.iter().map(|(_, s)| s.peak_occupancy).max().unwrap(),
+        )
+    }
+}
@@ -0,0 +1,96 @@
+use std::collections::VecDeque;
+
+use serde::Deserialize;
+
+use crate::{cache::AccessKind, cpu::Cpu, hierarchy::Hierarchy, trace::Instr};
+
+#[derive(Deserialize, Clone)]
+pub struct OooConfig {
+    rob_size: usize,
+    /// Instructions dispatched and retired per cycle
+    width: usize,
+}
+
+/// A dataflow out-of-order core: instructions dispatch in order into a ROB,
+/// execute once their source registers are ready, and retire in order
+pub struct Ooo {
+    rob_size: usize,
+    width: usize,
+    /// Retire cycle of every instruction in the ROB, oldest first
+    rob: VecDeque<u64>,
+    reg_ready: [u64; 256],
+    /// Earliest cycle the next instruction can dispatch
+    frontend: u64,
+    /// The last cycle something dispatched or retired, and how many did
+    dispatch_slot: (u64, usize),
+    retire_slot: (u64, usize),
+}
+
+impl Ooo {
+    pub fn new(config: OooConfig) -> Self {
+        assert!(config.rob_size > 0 && config.width > 0);
+        Ooo {
+            rob_size: config.rob_size,
+            width: config.width,
+            rob: VecDeque::with_capacity(config.rob_size),
+            reg_ready: [0; 256],
+            frontend: 0,
+            dispatch_slot: (0, 0),
+            retire_slot: (0, 0),
+        }
+    }
+
+    pub fn operate(&mut self, cpu: &mut Cpu, hierarchy: &mut Hierarchy, instr: &Instr) {
+        cpu.ip = instr.ip;
+
+        // An instruction fetch miss holds up the front end until the line arrives
+        let fetch_ready =
+            hierarchy.access(cpu, instr.ip as usize, AccessKind::Fetch, self.frontend);
+        let fetched = fetch_ready - hierarchy.hit_latency(cpu, AccessKind::Fetch);
+
+        let mut dispatch = self.frontend.max(fetched);
+        if self.rob.len() == self.rob_size {
+            dispatch = dispatch.max(self.rob.pop_front().unwrap());
+        }
+        let dispatch = take_slot(&mut self.dispatch_slot, dispatch, self.width);
+        self.frontend = dispatch;
+
+        let operands = instr
+            .source_registers
+            .iter()
+            .filter(|&&reg| reg != 0)
+            .map(|&reg| self.reg_ready[reg as usize])
+            .max()
+            .unwrap_or(0);
+        let execute = dispatch.max(operands);
+
+        // Loads hold up the result, stores drain in the background
+        let mut complete = execute + 1;
+        for (addr, kind) in instr.data_addresses() {
+            let ready = hierarchy.access(cpu, addr, kind, execute);
+            if kind == AccessKind::Load {
+                complete = complete.max(ready);
+            }
+        }
+        for &reg in instr.destination_registers.iter().filter(|&&reg| reg != 0) {
+            self.reg_ready[reg as usize] = complete;
+        }
+
+        let retire = take_slot(&mut self.retire_slot, complete, self.width);
+        self.rob.push_back(retire);
+        cpu.cycle = retire;
+        cpu.instr_idx += 1;
+    }
+}
+
+/// Finds the first cycle from `earliest` on that still has one of `width` slots
+fn take_slot(slot: &mut (u64, usize), earliest: u64, width: usize) -> u64 {
+    if earliest > slot.0 {
+        *slot = (earliest, 0);
+    }
+    if slot.1 == width {
+        *slot = (slot.0 + 1, 0);
+    }
+    slot.1 += 1;
+    slot.0
+}
@@ -0,0 +1,55 @@
+use super::Prefetch;
+
+const TABLE_SIZE: usize = 1024;
+const DEGREE: i64 = 3;
+
+#[derive(Debug, Clone, Copy, Default)]
+struct StrideEntry {
+    ip: u64,
+    last_line: i64,
+    stride: i64,
+}
+
+/// Prefetches ahead of instructions that keep striding by the same number of lines
+#[derive(Debug)]
+pub struct IpStride {
+    block_size: usize,
+    table: Vec<StrideEntry>,
+}
+
+impl IpStride {
+    pub fn new(block_size: usize) -> Self {
+        IpStride {
+            block_size,
+            table: vec![StrideEntry::default(); TABLE_SIZE],
+        }
+    }
+}
+
+impl Prefetch for IpStride {
+    fn access(&mut self, line: usize, ip: u64, _hit: bool) -> Vec<usize> {
+        let line_num = (line / self.block_size) as i64;
+        let entry = &mut self.table[(ip >> 2) as usize % TABLE_SIZE];
+        if entry.ip != ip {
+            *entry = StrideEntry {
+                ip,
+                last_line: line_num,
+                stride: 0,
+            };
+            return Vec::new();
+        }
+
+        let stride = line_num - entry.last_line;
+        let confirmed = stride != 0 && stride == entry.stride;
+        entry.last_line = line_num;
+        entry.stride = stride;
+        if !confirmed {
+            return Vec::new();
+        }
+        (1..=DEGREE)
+            .map(|k| line_num + stride * k)
+            .filter(|&target| target >= 0)
+            .map(|target| target as usize * self.block_size)
+            .collect()
+    }
+}
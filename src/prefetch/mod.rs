@@ -0,0 +1,156 @@
+pub mod ip_stride;
+pub mod next_line;
+pub mod stream;
+
+use std::fmt::Debug;
+
+use serde::Serialize;
+
+use crate::{cache::AccessKind, simpoint};
+
+use self::{ip_stride::IpStride, next_line::NextLine, stream::Stream};
+
+pub trait Prefetch: Debug {
+    /// Called on every demand access to the cache with the line it touched,
+    /// returns the lines to prefetch into the cache
+    fn access(&mut self, line: usize, ip: u64, hit: bool) -> Vec<usize>;
+}
+
+/// A cache's prefetcher, along with what it takes to judge it
+#[derive(Debug)]
+pub struct Prefetcher {
+    prefetch: Box<dyn Prefetch>,
+    /// Lines evicted to make room for a prefetch, until they are filled again.
+    /// Direct mapped with a slot per line the cache holds, so a line is
+    /// forgotten once as many others have been displaced after it.
+    displaced: Vec<Option<usize>>,
+    block_size: usize,
+    issued: u64,
+    /// Prefetches dropped because no MSHR was free
+    dropped: u64,
+    pollution: u64,
+}
+
+#[derive(Serialize)]
+pub struct PrefetchStats {
+    issued: u64,
+    dropped: u64,
+    fills: u64,
+    useful: u64,
+    useless: u64,
+    /// Prefetched lines used by a demand access
+    accuracy: f64,
+    /// Demand misses avoided out of those there would have been
+    coverage: f64,
+    /// Demand misses on lines a prefetch evicted
+    pollution: u64,
+    /// The cache's demand misses
+    #[serde(skip)]
+    misses: u64,
+}
+
+impl Prefetcher {
+    pub fn new(name: &str, block_size: usize, n_lines: usize) -> Self {
+        let prefetch = match name {
+            "next_line" => Box::new(NextLine::new(block_size)) as Box<dyn Prefetch>,
+            "ip_stride" => Box::new(IpStride::new(block_size)) as Box<dyn Prefetch>,
+            "stream" => Box::new(Stream::new(block_size)) as Box<dyn Prefetch>,
+            _ => panic!("Unrecognized prefetcher: {}", name),
+        };
+        Prefetcher {
+            prefetch,
+            displaced: vec![None; n_lines],
+            block_size,
+            issued: 0,
+            dropped: 0,
+            pollution: 0,
+        }
+    }
+
+    pub fn access(&mut self, line: usize, ip: u64, hit: bool) -> Vec<usize> {
+        let lines = self.prefetch.access(line, ip, hit);
+        self.issued += lines.len() as u64;
+        lines
+    }
+
+    pub fn dropped(&mut self) {
+        self.dropped += 1;
+    }
+
+    /// Records a miss that allocated `line`, and the line it displaced
+    pub fn fill(&mut self, line: usize, kind: AccessKind, evicted: Option<usize>) {
+        let slot = self.slot(line);
+        if self.displaced[slot] == Some(line) {
+            self.displaced[slot] = None;
+            if kind.is_demand() {
+                self.pollution += 1;
+            }
+        }
+        if let Some(evicted) = evicted.filter(|_| kind == AccessKind::Prefetch) {
+            let slot = self.slot(evicted);
+            self.displaced[slot] = Some(evicted);
+        }
+    }
+
+    fn slot(&self, line: usize) -> usize {
+        line / self.block_size % self.displaced.len()
+    }
+
+    pub fn clear_stats(&mut self) {
+        self.issued = 0;
+        self.dropped = 0;
+        self.pollution = 0;
+    }
+
+    /// Fills, useful and useless prefetches are counted by the cache's blocks
+    pub fn make_stats(&self, fills: u64, useful: u64, useless: u64, misses: u64) -> PrefetchStats {
+        PrefetchStats::new(
+            [
+                self.issued,
+                self.dropped,
+                fills,
+                useful,
+                useless,
+                self.pollution,
+                misses,
+            ]
+            .map(|count| count as f64),
+        )
+    }
+}
+
+impl PrefetchStats {
+    /// Counts are fractional when they come from weighted regions
+    fn new(counts: [f64; 7]) -> Self {
+        let [issued, dropped, fills, useful, useless, pollution, misses] = counts;
+        PrefetchStats {
+            issued: issued.round() as u64,
+            dropped: dropped.round() as u64,
+            fills: fills.round() as u64,
+            useful: useful.round() as u64,
+            useless: useless.round() as u64,
+            accuracy: useful / fills,
+            coverage: useful / (useful + misses),
+            pollution: pollution.round() as u64,
+            misses: misses.round() as u64,
+        }
+    }
+
+    /// Regions' counts summed by weight
+    pub fn weighted(regions: &[(f64, &PrefetchStats)]) -> Self {
+        let counts = |s: &PrefetchStats| {
+            [
+                s.issued,
+                s.dropped,
+                s.fills,
+                s.useful,
+                s.useless,
+                s.pollution,
+                s.misses,
+            ]
+        };
+        PrefetchStats::new(std::array::from_fn(|idx| {
+            simpoint::sum(regions, |s| counts(s)[idx] as f64)
+        }))
+    }
+}
@@ -0,0 +1,19 @@
+use super::Prefetch;
+
+/// Prefetches the line after every line accessed
+#[derive(Debug)]
+pub struct NextLine {
+    block_size: usize,
+}
+
+impl NextLine {
+    pub fn new(block_size: usize) -> Self {
+        NextLine { block_size }
+    }
+}
+
+impl Prefetch for NextLine {
+    fn access(&mut self, line: usize, _ip: u64, _hit: bool) -> Vec<usize> {
+        vec![line + self.block_size]
+    }
+}
@@ -0,0 +1,79 @@
+use std::collections::VecDeque;
+
+use super::Prefetch;
+
+const PAGE_SIZE: usize = 4096;
+const TRACKERS: usize = 64;
+const DEGREE: i64 = 4;
+/// Accesses in the same direction before a stream starts prefetching
+const CONFIDENCE: u8 = 2;
+
+#[derive(Debug)]
+struct Tracker {
+    page: usize,
+    last_offset: i64,
+    direction: i64,
+    confidence: u8,
+}
+
+/// Follows ascending or descending runs of lines within a page
+#[derive(Debug)]
+pub struct Stream {
+    block_size: usize,
+    /// Most recently used first
+    trackers: VecDeque<Tracker>,
+}
+
+impl Stream {
+    pub fn new(block_size: usize) -> Self {
+        Stream {
+            block_size,
+            trackers: VecDeque::with_capacity(TRACKERS),
+        }
+    }
+}
+
+impl Prefetch for Stream {
+    fn access(&mut self, line: usize, _ip: u64, _hit: bool) -> Vec<usize> {
+        let page = line / PAGE_SIZE;
+        let offset = ((line % PAGE_SIZE) / self.block_size) as i64;
+
+        let Some(idx) = self.trackers.iter().position(|t| t.page == page) else {
+            if self.trackers.len() == TRACKERS {
+                self.trackers.pop_back();
+            }
+            self.trackers.push_front(Tracker {
+                page,
+                last_offset: offset,
+                direction: 0,
+                confidence: 0,
+            });
+            return Vec::new();
+        };
+        let mut tracker = self.trackers.remove(idx).unwrap();
+
+        let direction = (offset - tracker.last_offset).signum();
+        if direction != 0 {
+            if direction == tracker.direction {
+                tracker.confidence = (tracker.confidence + 1).min(CONFIDENCE);
+            } else {
+                tracker.direction = direction;
+                tracker.confidence = 0;
+            }
+            tracker.last_offset = offset;
+        }
+
+        let lines_per_page = (PAGE_SIZE / self.block_size) as i64;
+        let lines = if tracker.confidence >= CONFIDENCE {
+            (1..=DEGREE)
+                .map(|k| offset + tracker.direction * k)
+                .take_while(|&target| (0..lines_per_page).contains(&target))
+                .map(|target| page * PAGE_SIZE + target as usize * self.block_size)
+                .collect()
+        } else {
+            Vec::new()
+        };
+        self.trackers.push_front(tracker);
+        lines
+    }
+}
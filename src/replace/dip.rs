@@ -0,0 +1,98 @@
+use std::collections::VecDeque;
+
+use crate::{cache::AccessKind, cpu::Cpu};
+
+use super::{duel::Duel, Access, Eviction, MakeS, Replace, Set};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Insertion {
+    /// Always at the LRU position
+    Lru,
+    /// At MRU with probability epsilon, otherwise at LRU
+    Bimodal,
+    /// Set dueling between MRU insertion and bimodal
+    Dynamic,
+}
+
+/// LRU replacement with thrash-resistant insertion (Qureshi et al., ISCA 2007)
+pub struct Dip {
+    insertion: Insertion,
+    epsilon: f64,
+    rng: fastrand::Rng,
+    duel: Duel,
+}
+
+impl Dip {
+    pub fn new(insertion: Insertion, epsilon: f64) -> Self {
+        assert!(
+            (0.0..=1.0).contains(&epsilon),
+            "BIP epsilon must be between 0 and 1"
+        );
+        Dip {
+            insertion,
+            epsilon,
+            rng: fastrand::Rng::with_seed(0),
+            duel: Duel::new(["lru", "bip"]),
+        }
+    }
+
+    /// Whether a fill into this set goes in at MRU
+    fn insert_at_mru(&mut self, set: usize, kind: AccessKind) -> bool {
+        let bimodal = match self.insertion {
+            Insertion::Lru => return false,
+            Insertion::Bimodal => true,
+            Insertion::Dynamic => self.duel.use_second(set, kind),
+        };
+        !bimodal || self.rng.f64() < self.epsilon
+    }
+}
+
+impl Replace<DipSetData, ()> for Dip {
+    fn find_victim(
+        &mut self,
+        _cpu: &Cpu,
+        _access: &Access,
+        set: &mut Set<DipSetData, ()>,
+    ) -> usize {
+        *set.data.ru_order.back().unwrap() as usize
+    }
+
+    fn on_hit(&mut self, _cpu: &Cpu, _access: &Access, set: &mut Set<DipSetData, ()>, way: usize) {
+        let lru_queue = &mut set.data.ru_order;
+        lru_queue.retain(|&w| w as usize != way);
+        lru_queue.push_front(way as u16);
+    }
+
+    fn on_evict(&mut self, set: &mut Set<DipSetData, ()>, way: usize, _cause: Eviction) {
+        set.data.ru_order.retain(|&w| w as usize != way);
+    }
+
+    fn on_fill(&mut self, _cpu: &Cpu, access: &Access, set: &mut Set<DipSetData, ()>, way: usize) {
+        if self.insert_at_mru(access.set, access.kind) {
+            set.data.ru_order.push_front(way as u16);
+        } else {
+            set.data.ru_order.push_back(way as u16);
+        }
+    }
+
+    fn clear_stats(&mut self) {
+        self.duel.clear_stats();
+    }
+
+    fn stats(&self) -> Option<serde_json::Value> {
+        (self.insertion == Insertion::Dynamic).then(|| self.duel.stats())
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct DipSetData {
+    ru_order: VecDeque<u16>,
+}
+
+impl MakeS for DipSetData {
+    fn new(n_ways: usize) -> Self {
+        DipSetData {
+            ru_order: VecDeque::with_capacity(n_ways),
+        }
+    }
+}
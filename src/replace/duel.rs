@@ -0,0 +1,84 @@
+use serde_json::{Map, Value};
+
+use crate::cache::AccessKind;
+
+/// One leader set of each kind in this many sets
+const DUEL_PERIOD: usize = 64;
+const PSEL_BITS: u32 = 10;
+const PSEL_MAX: u32 = (1 << PSEL_BITS) - 1;
+
+/// Set dueling (Qureshi et al., ISCA 2007) between two insertion policies.
+/// A few leader sets always use one or the other, and the rest follow
+/// whichever leaders have missed less.
+pub struct Duel {
+    names: [&'static str; 2],
+    psel: u32,
+    leader_misses: [u64; 2],
+    follower_fills: [u64; 2],
+}
+
+impl Duel {
+    pub fn new(names: [&'static str; 2]) -> Self {
+        Duel {
+            names,
+            psel: 1 << (PSEL_BITS - 1),
+            leader_misses: [0; 2],
+            follower_fills: [0; 2],
+        }
+    }
+
+    /// Whether a fill into this set uses the second policy, training PSEL on
+    /// demand misses to the leader sets
+    pub fn use_second(&mut self, set: usize, kind: AccessKind) -> bool {
+        match set % DUEL_PERIOD {
+            0 => {
+                if kind.is_demand() {
+                    self.leader_misses[0] += 1;
+                    self.psel = (self.psel + 1).min(PSEL_MAX);
+                }
+                false
+            }
+            1 => {
+                if kind.is_demand() {
+                    self.leader_misses[1] += 1;
+                    self.psel = self.psel.saturating_sub(1);
+                }
+                true
+            }
+            _ => {
+                // The first policy's leaders missing more pushes PSEL up,
+                // towards the second
+                let second = self.psel > PSEL_MAX / 2;
+                self.follower_fills[second as usize] += 1;
+                second
+            }
+        }
+    }
+
+    pub fn clear_stats(&mut self) {
+        self.leader_misses = [0; 2];
+        self.follower_fills = [0; 2];
+    }
+
+    pub fn stats(&self) -> Value {
+        let [first, second] = self.names;
+        let mut stats = Map::new();
+        stats.insert("psel".into(), self.psel.into());
+        // The insertion policy followers use at the end of the run
+        let winner = self.names[(self.psel > PSEL_MAX / 2) as usize];
+        stats.insert("winner".into(), winner.into());
+        stats.insert(
+            format!("{}_leader_misses", first),
+            self.leader_misses[0].into(),
+        );
+        stats.insert(
+            format!("{}_leader_misses", second),
+            self.leader_misses[1].into(),
+        );
+        // Fraction of follower fills inserted with the second policy
+        let fills = self.follower_fills[1] as f64
+            / (self.follower_fills[0] + self.follower_fills[1]) as f64;
+        stats.insert(format!("follower_{}_fills", second), fills.into());
+        Value::Object(stats)
+    }
+}
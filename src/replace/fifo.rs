@@ -0,0 +1,53 @@
+use crate::cpu::Cpu;
+
+use super::{Access, MakeS, Replace, Set};
+
+/// Evicts the line that was filled first, however much it has been used
+pub struct Fifo {
+    fills: u64,
+}
+
+impl Fifo {
+    pub fn new() -> Self {
+        Fifo { fills: 0 }
+    }
+}
+
+impl Replace<FifoSetData, FifoBlockData> for Fifo {
+    fn find_victim(
+        &mut self,
+        _cpu: &Cpu,
+        _access: &Access,
+        set: &mut Set<FifoSetData, FifoBlockData>,
+    ) -> usize {
+        (0..set.blocks.len())
+            .min_by_key(|&way| set.blocks[way].repl_block.filled)
+            .unwrap()
+    }
+
+    fn on_fill(
+        &mut self,
+        _cpu: &Cpu,
+        _access: &Access,
+        set: &mut Set<FifoSetData, FifoBlockData>,
+        way: usize,
+    ) {
+        set.blocks[way].repl_block.filled = self.fills;
+        self.fills += 1;
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct FifoSetData {}
+
+impl MakeS for FifoSetData {
+    fn new(_n_ways: usize) -> Self {
+        FifoSetData {}
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct FifoBlockData {
+    /// Order the line was filled in
+    filled: u64,
+}
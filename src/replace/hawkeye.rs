@@ -0,0 +1,250 @@
+use std::collections::HashMap;
+
+use serde::Serialize;
+
+use crate::cpu::Cpu;
+
+use super::{
+    sampled::{self, SampledSets},
+    Access, Eviction, MakeS, Replace, Set,
+};
+
+type PredCounter = u8;
+const PRED_BITS: u32 = 11; // 2K counters
+const PRED_MAX: PredCounter = 7;
+/// Lines from a PC whose counter is at least this are cache-friendly
+const PRED_FRIENDLY: PredCounter = 4;
+const MAX_RRPV: u8 = 7;
+/// Sets OPTgen reconstructs Belady's decisions on
+const SAMPLED_SETS: usize = 64;
+/// OPTgen looks back this many accesses per way of the set
+const HISTORY_PER_WAY: usize = 8;
+
+/// Reconstructs what Belady would have done on one sampled set
+struct OptGen {
+    /// Lines OPT would hold at each of the recent accesses, as a ring
+    occupancy: Vec<u8>,
+    /// Accesses to the set so far
+    time: u64,
+    /// Last access time and PC signature of each line seen recently
+    sampler: HashMap<usize, (u64, u32)>,
+}
+
+impl OptGen {
+    fn new(n_ways: usize) -> Self {
+        OptGen {
+            occupancy: vec![0; HISTORY_PER_WAY * n_ways],
+            time: 0,
+            sampler: HashMap::new(),
+        }
+    }
+
+    /// Returns the PC signature that last touched the line, and whether OPT
+    /// would have kept the line since then
+    fn access(&mut self, line: usize, signature: u32, n_ways: usize) -> Option<(u32, bool)> {
+        let history = self.occupancy.len() as u64;
+        let now = self.time;
+        self.time += 1;
+        self.occupancy[(now % history) as usize] = 0;
+
+        let last = self.sampler.insert(line, (now, signature));
+        if self.sampler.len() > 2 * self.occupancy.len() {
+            self.sampler
+                .retain(|_, &mut (time, _)| now - time < history);
+        }
+
+        let (then, last_signature) = last?;
+        if now - then >= history {
+            // Too long ago for OPT to have kept it
+            return Some((last_signature, false));
+        }
+        let interval = (then..now).map(|t| (t % history) as usize);
+        let kept = interval
+            .clone()
+            .all(|idx| (self.occupancy[idx] as usize) < n_ways);
+        if kept {
+            interval.for_each(|idx| self.occupancy[idx] += 1);
+        }
+        Some((last_signature, kept))
+    }
+}
+
+#[derive(Serialize)]
+struct HawkeyeStats {
+    /// Accesses to sampled sets with an earlier access OPTgen could judge
+    optgen_accesses: u64,
+    optgen_hits: u64,
+    fills: u64,
+    friendly_fills: u64,
+    /// Friendly lines evicted anyway, training their PC towards averse
+    detrains: u64,
+}
+
+/// Learns from OPTgen which PCs fill lines Belady would keep (Jain and Lin, ISCA 2016)
+pub struct Hawkeye {
+    predictor: Vec<PredCounter>,
+    optgen: SampledSets<OptGen>,
+    /// Signature of the access being handled, and whether it is friendly
+    current: (u32, bool),
+    optgen_accesses: u64,
+    optgen_hits: u64,
+    fills: u64,
+    friendly_fills: u64,
+    detrains: u64,
+}
+
+impl Hawkeye {
+    pub fn new(n_sets: usize, n_ways: usize) -> Self {
+        Hawkeye {
+            predictor: vec![PRED_FRIENDLY; 1 << PRED_BITS],
+            optgen: SampledSets::new(n_sets, SAMPLED_SETS, || OptGen::new(n_ways)),
+            current: (0, false),
+            optgen_accesses: 0,
+            optgen_hits: 0,
+            fills: 0,
+            friendly_fills: 0,
+            detrains: 0,
+        }
+    }
+
+    fn signature(ip: u64) -> u32 {
+        let mask = (1u64 << PRED_BITS) - 1;
+        ((ip ^ (ip >> PRED_BITS) ^ (ip >> (2 * PRED_BITS))) & mask) as u32
+    }
+
+    fn train(&mut self, signature: u32, friendly: bool) {
+        let counter = &mut self.predictor[signature as usize];
+        if friendly {
+            *counter = (*counter + 1).min(PRED_MAX);
+        } else {
+            *counter = counter.saturating_sub(1);
+        }
+    }
+
+    /// Lets OPTgen see a demand access if its set is sampled
+    fn sample(&mut self, set: usize, line: usize, signature: u32, n_ways: usize) {
+        let Some(optgen) = self.optgen.get(set) else {
+            return;
+        };
+        if let Some((last_signature, kept)) = optgen.access(line, signature, n_ways) {
+            self.optgen_accesses += 1;
+            self.optgen_hits += kept as u64;
+            self.train(last_signature, kept);
+        }
+    }
+}
+
+impl Replace<HawkeyeSetData, HawkeyeBlockData> for Hawkeye {
+    fn find_victim(
+        &mut self,
+        _cpu: &Cpu,
+        _access: &Access,
+        set: &mut Set<HawkeyeSetData, HawkeyeBlockData>,
+    ) -> usize {
+        // Averse lines go first, otherwise the oldest friendly line
+        (0..set.blocks.len())
+            .max_by_key(|&way| set.blocks[way].repl_block.rrpv)
+            .unwrap()
+    }
+
+    fn on_access(
+        &mut self,
+        cpu: &Cpu,
+        access: &Access,
+        set: &mut Set<HawkeyeSetData, HawkeyeBlockData>,
+    ) {
+        let signature = Hawkeye::signature(cpu.ip);
+        let has_pc = sampled::has_pc(access.kind);
+        if has_pc {
+            self.sample(access.set, access.line, signature, set.blocks.len());
+        }
+        let friendly = has_pc && self.predictor[signature as usize] >= PRED_FRIENDLY;
+        self.current = (signature, friendly);
+    }
+
+    fn on_hit(
+        &mut self,
+        _cpu: &Cpu,
+        access: &Access,
+        set: &mut Set<HawkeyeSetData, HawkeyeBlockData>,
+        way: usize,
+    ) {
+        if sampled::has_pc(access.kind) {
+            let (signature, friendly) = self.current;
+            let data = &mut set.blocks[way].repl_block;
+            data.signature = signature;
+            data.rrpv = if friendly { 0 } else { MAX_RRPV };
+        }
+    }
+
+    fn on_evict(
+        &mut self,
+        set: &mut Set<HawkeyeSetData, HawkeyeBlockData>,
+        way: usize,
+        cause: Eviction,
+    ) {
+        let data = &set.blocks[way].repl_block;
+        if cause == Eviction::Replaced && data.rrpv < MAX_RRPV {
+            self.detrains += 1;
+            self.train(data.signature, false);
+        }
+    }
+
+    fn on_fill(
+        &mut self,
+        _cpu: &Cpu,
+        _access: &Access,
+        set: &mut Set<HawkeyeSetData, HawkeyeBlockData>,
+        way: usize,
+    ) {
+        let (signature, friendly) = self.current;
+        if friendly {
+            // Friendly lines age relative to each other
+            for block in set.blocks.iter_mut().filter(|b| b.valid) {
+                if block.repl_block.rrpv < MAX_RRPV - 1 {
+                    block.repl_block.rrpv += 1;
+                }
+            }
+        }
+        self.fills += 1;
+        self.friendly_fills += friendly as u64;
+        set.blocks[way].repl_block = HawkeyeBlockData {
+            rrpv: if friendly { 0 } else { MAX_RRPV },
+            signature,
+        };
+    }
+
+    fn clear_stats(&mut self) {
+        self.optgen_accesses = 0;
+        self.optgen_hits = 0;
+        self.fills = 0;
+        self.friendly_fills = 0;
+        self.detrains = 0;
+    }
+
+    fn stats(&self) -> Option<serde_json::Value> {
+        let stats = HawkeyeStats {
+            optgen_accesses: self.optgen_accesses,
+            optgen_hits: self.optgen_hits,
+            fills: self.fills,
+            friendly_fills: self.friendly_fills,
+            detrains: self.detrains,
+        };
+        Some(serde_json::to_value(stats).unwrap())
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct HawkeyeSetData {}
+
+impl MakeS for HawkeyeSetData {
+    fn new(_n_ways: usize) -> Self {
+        HawkeyeSetData {}
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct HawkeyeBlockData {
+    rrpv: u8,
+    signature: u32,
+}
@@ -0,0 +1,79 @@
+use crate::cpu::Cpu;
+
+use super::{Access, MakeS, Replace, Set};
+
+/// Evicts the line used least often since it was filled. With aging, every
+/// set halves its counts after a given number of accesses to it, so lines
+/// that were hot long ago can leave.
+pub struct Lfu {
+    aging_period: Option<u64>,
+}
+
+impl Lfu {
+    pub fn new(aging_period: Option<u64>) -> Self {
+        assert!(aging_period != Some(0), "LFU aging period must be positive");
+        Lfu { aging_period }
+    }
+}
+
+impl Replace<LfuSetData, LfuBlockData> for Lfu {
+    fn find_victim(
+        &mut self,
+        _cpu: &Cpu,
+        _access: &Access,
+        set: &mut Set<LfuSetData, LfuBlockData>,
+    ) -> usize {
+        (0..set.blocks.len())
+            .min_by_key(|&way| set.blocks[way].repl_block.count)
+            .unwrap()
+    }
+
+    fn on_access(&mut self, _cpu: &Cpu, _access: &Access, set: &mut Set<LfuSetData, LfuBlockData>) {
+        let Some(period) = self.aging_period else {
+            return;
+        };
+        set.data.accesses += 1;
+        if set.data.accesses.is_multiple_of(period) {
+            for block in set.blocks.iter_mut() {
+                block.repl_block.count /= 2;
+            }
+        }
+    }
+
+    fn on_hit(
+        &mut self,
+        _cpu: &Cpu,
+        _access: &Access,
+        set: &mut Set<LfuSetData, LfuBlockData>,
+        way: usize,
+    ) {
+        let count = &mut set.blocks[way].repl_block.count;
+        *count = count.saturating_add(1);
+    }
+
+    fn on_fill(
+        &mut self,
+        _cpu: &Cpu,
+        _access: &Access,
+        set: &mut Set<LfuSetData, LfuBlockData>,
+        way: usize,
+    ) {
+        set.blocks[way].repl_block.count = 1;
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct LfuSetData {
+    accesses: u64,
+}
+
+impl MakeS for LfuSetData {
+    fn new(_n_ways: usize) -> Self {
+        LfuSetData { accesses: 0 }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct LfuBlockData {
+    count: u32,
+}
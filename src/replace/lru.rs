@@ -1,11 +1,8 @@
 use std::collections::VecDeque;
 
-use crate::{
-    cache::{Addr, Cache, IsCache},
-    cpu::Cpu,
-};
+use crate::cpu::Cpu;
 
-use super::{AccessResult, MakeS, Replace};
+use super::{Access, Eviction, MakeS, Replace, Set};
 
 pub struct Lru {}
 
@@ -16,54 +13,27 @@ impl Lru {
 }
 
 impl Replace<LruSetData, ()> for Lru {
-    fn access(cpu: &mut Cpu, cache: &mut Cache<LruSetData, (), Self>, addr: Addr) -> AccessResult {
-        let set_range = cache.get_set(addr.set);
-        // println!("Addr: {addr:#?}");
-        let set_slice = &mut cache.blocks[set_range];
-        let lru_queue = &mut cache.set_data[addr.set].ru_order;
-        // let vacant_blocks = set_slice.iter().filter(|b| !b.valid).count();
-        // First, look for a hit
-        let hit = set_slice
-            .iter_mut()
-            .enumerate()
-            .find(|(_way, b)| b.valid && b.tag == addr.tag);
+    fn find_victim(
+        &mut self,
+        _cpu: &Cpu,
+        _access: &Access,
+        set: &mut Set<LruSetData, ()>,
+    ) -> usize {
+        *set.data.ru_order.back().unwrap() as usize
+    }
 
-        if let Some((hit_way, block)) = hit {
-            lru_queue.remove(
-                lru_queue
-                    .iter()
-                    .cloned()
-                    .enumerate()
-                    .find(|(_idx, way)| *way as usize == hit_way)
-                    .unwrap()
-                    .0,
-            );
-            lru_queue.push_front(hit_way as u16);
-            block.read(cpu);
-            AccessResult::Hit
-        } else {
-            // if vacant_blocks < cache.n_ways {
-            //     println!("Vacant: {vacant_blocks}");
-            // }
-            // Its a miss, lets allocate space for it
-            let (victim_way, victim) = if let Some(vacant_block) =
-                set_slice.iter_mut().enumerate().find(|(_way, b)| !b.valid)
-            {
-                // Empty Block, YAY
-                vacant_block
-            } else {
-                // No empty blocks, evict
-                let lru_way = lru_queue.pop_back().unwrap() as usize;
-                let victim_block = &mut set_slice[lru_way];
-                victim_block.evict(cpu);
-                (lru_way, victim_block)
-            };
-            lru_queue.push_front(victim_way as u16);
-            victim.apply(addr);
-            victim.alloc(cpu);
+    fn on_hit(&mut self, _cpu: &Cpu, _access: &Access, set: &mut Set<LruSetData, ()>, way: usize) {
+        let lru_queue = &mut set.data.ru_order;
+        lru_queue.retain(|&w| w as usize != way);
+        lru_queue.push_front(way as u16);
+    }
 
-            AccessResult::Miss
-        }
+    fn on_evict(&mut self, set: &mut Set<LruSetData, ()>, way: usize, _cause: Eviction) {
+        set.data.ru_order.retain(|&w| w as usize != way);
+    }
+
+    fn on_fill(&mut self, _cpu: &Cpu, _access: &Access, set: &mut Set<LruSetData, ()>, way: usize) {
+        set.data.ru_order.push_front(way as u16);
     }
 }
 
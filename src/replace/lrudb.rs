@@ -5,11 +5,11 @@ use std::{
 };
 
 use crate::{
-    cache::{Addr, Cache, IsCache},
+    cache::{AccessKind, Addr, Block, Cache, Evicted, IsCache},
     cpu::Cpu,
 };
 
-use super::{AccessResult, MakeS, Replace};
+use super::{sdbp::DeadBlockStats, AccessResult, MakeS, Policy};
 
 fn dual_slice_mut<'a, T>(
     data: &'a mut [T],
@@ -39,12 +39,14 @@ const TABLE_SIZE: usize = 1 << 15; // 32K Table
 
 pub struct Lrudb {
     pred_table: Vec<PredCounter>,
+    prediction: DeadBlockStats,
 }
 
 impl Lrudb {
     pub fn new() -> Self {
         Lrudb {
             pred_table: vec![0; TABLE_SIZE],
+            prediction: DeadBlockStats::default(),
         }
     }
 }
@@ -69,12 +71,16 @@ fn use_lru(queue: &mut VecDeque<u16>) -> usize {
     lru as usize
 }
 
-impl Replace<LrudbSetData, LrudbBlockData> for Lrudb {
+/// Lines can be displaced into the adjacent set, so Lrudb looks lines up
+/// and picks victims across both sets itself
+impl Policy<LrudbSetData, LrudbBlockData> for Lrudb {
     fn access(
         cpu: &mut Cpu,
         cache: &mut Cache<LrudbSetData, LrudbBlockData, Self>,
         addr: Addr,
+        kind: AccessKind,
     ) -> AccessResult {
+        let allocate = cache.allocates(kind);
         let set_range = cache.get_set(addr.set);
         let adjacent_set = addr.set ^ (1 << 3);
         let adj_set_range = cache.get_set(adjacent_set);
@@ -102,6 +108,16 @@ impl Replace<LrudbSetData, LrudbBlockData> for Lrudb {
                 .find(|(_way, b)| b.valid && b.repl_block.receiver && b.tag == addr.tag)
                 .map(|(adj_way, adj_block)| {
                     // Receiver block in adj set matches
+                    if let Some(vacant_way) = set_slice.iter().position(|b| !b.valid) {
+                        // Room at home, move it back without displacing anything
+                        adj_queue.retain(|&w| w as usize != adj_way);
+                        main_queue.push_back(vacant_way as u16);
+                        let main_block = &mut set_slice[vacant_way];
+                        std::mem::swap(main_block, adj_block);
+                        std::mem::swap(&mut main_block.block_stats, &mut adj_block.block_stats);
+                        main_block.repl_block.receiver = false;
+                        return vacant_way;
+                    }
                     let main_victim_way = *main_queue.back().unwrap() as usize;
                     let main_victim = &mut set_slice[main_victim_way];
                     move_to_front(adj_queue, adj_way);
@@ -110,7 +126,6 @@ impl Replace<LrudbSetData, LrudbBlockData> for Lrudb {
 
                     main_victim.repl_block.receiver = false;
                     adj_block.repl_block.receiver = !adj_block.repl_block.receiver;
-                    main_victim.valid;
                     main_victim_way
                 })
         });
@@ -119,75 +134,123 @@ impl Replace<LrudbSetData, LrudbBlockData> for Lrudb {
         if let Some(hit_way) = hit {
             move_to_front(main_queue, hit_way);
             let block = &mut set_slice[hit_way];
-            block.read(cpu);
+            block.read(cpu, kind);
+            if kind.is_demand() {
+                cache.repl.prediction.hit(block.repl_block.dead);
+            }
             block.repl_block.access_block(&mut cache.repl.pred_table);
             block
                 .repl_block
                 .update_trace(cpu.ip as usize, &mut cache.repl.pred_table);
             AccessResult::Hit
+        } else if !allocate {
+            AccessResult::Miss(None)
         } else {
             // Its a miss, lets allocate space for it
-            let (victim_way, victim) = if let Some(vacant_block) =
+            let (victim_way, victim, evicted) = if let Some((way, vacant_block)) =
                 set_slice.iter_mut().enumerate().find(|(_way, b)| !b.valid)
             {
                 // Empty Block, YAY
-                vacant_block
+                (way, vacant_block, None)
             } else {
                 // No empty blocks, evict
                 let lru_way = main_queue.pop_back().unwrap() as usize;
                 let main_victim_block = &mut set_slice[lru_way];
 
-                let adj_victim = if let Some(adj) = adj_set_slice
+                // Receivers in the adjacent set were displaced from this set
+                let home_set = |b: &Block<LrudbBlockData>| {
+                    if b.repl_block.receiver {
+                        addr.set
+                    } else {
+                        adjacent_set
+                    }
+                };
+                let (adj_victim, evicted) = if let Some(adj) = adj_set_slice
                     .iter_mut()
                     .enumerate()
                     .find(|(_way, b)| !b.valid)
                 {
                     adj_queue.push_front(adj.0 as u16);
-                    adj.1
+                    (adj.1, None)
                 } else if let Some((adj_way, adj_block)) = adj_set_slice
                     .iter_mut()
                     .enumerate()
                     .find(|(_way, b)| b.repl_block.dead)
                 {
                     move_to_front(adj_queue, adj_way);
-                    adj_block.evict(cpu);
+                    cache.repl.prediction.evicted(true);
+                    let evicted = adj_block.evict(cpu, home_set(adj_block));
                     adj_block
                         .repl_block
                         .replace_block(&mut cache.repl.pred_table);
-                    adj_block
+                    (adj_block, Some(evicted))
                 } else {
                     let adj_lru_way = use_lru(adj_queue);
-                    if adj_lru_way > adj_set_slice.len() {
-                        println!("{}", adj_lru_way);
-                    }
                     let adj_block = &mut adj_set_slice[adj_lru_way];
-                    adj_block.evict(cpu);
+                    cache.repl.prediction.evicted(false);
+                    let evicted = adj_block.evict(cpu, home_set(adj_block));
                     adj_block
                         .repl_block
                         .replace_block(&mut cache.repl.pred_table);
-                    adj_block
+                    (adj_block, Some(evicted))
                 };
                 let main_stats = main_victim_block.block_stats;
                 let adj_stats = adj_victim.block_stats;
                 *adj_victim = std::mem::take(main_victim_block);
                 adj_victim.valid = true;
-                adj_victim.repl_block.receiver = true;
+                // A receiver evicted from here is going back to its own set
+                adj_victim.repl_block.receiver = !adj_victim.repl_block.receiver;
                 adj_victim.block_stats = adj_stats;
                 main_victim_block.block_stats = main_stats;
 
-                (lru_way, main_victim_block)
+                (lru_way, main_victim_block, evicted)
             };
             main_queue.push_front(victim_way as u16);
-            victim.apply(addr);
+            victim.apply(addr, kind);
             victim.repl_block.receiver = false;
             victim.alloc(cpu);
             victim
                 .repl_block
                 .update_trace(cpu.ip as usize, &mut cache.repl.pred_table);
 
-            AccessResult::Miss
+            AccessResult::Miss(evicted)
         }
     }
+
+    fn invalidate(
+        cpu: &mut Cpu,
+        cache: &mut Cache<LrudbSetData, LrudbBlockData, Self>,
+        addr: Addr,
+    ) -> Option<Evicted> {
+        // The line is either in its own set or displaced into the adjacent one
+        let adjacent_set = addr.set ^ (1 << 3);
+        for (set, receiver) in [(addr.set, false), (adjacent_set, true)] {
+            let set_range = cache.get_set(set);
+            let found = cache.blocks[set_range]
+                .iter_mut()
+                .enumerate()
+                .find(|(_way, b)| {
+                    b.valid && b.repl_block.receiver == receiver && b.tag == addr.tag
+                });
+            if let Some((way, block)) = found {
+                let evicted = block.evict(cpu, addr.set);
+                block.valid = false;
+                block.repl_block.replace_block(&mut cache.repl.pred_table);
+                let queue = &mut cache.set_data[set].ru_order;
+                queue.retain(|&w| w as usize != way);
+                return Some(evicted);
+            }
+        }
+        None
+    }
+
+    fn clear_stats(&mut self) {
+        self.prediction = DeadBlockStats::default();
+    }
+
+    fn stats(&self) -> Option<serde_json::Value> {
+        Some(self.prediction.make_stats())
+    }
 }
 
 #[derive(Debug, Default)]
@@ -1,9 +1,22 @@
+pub mod dip;
+mod duel;
+pub mod fifo;
+pub mod hawkeye;
+pub mod lfu;
 pub mod lru;
 pub mod lrudb;
 pub mod nmru;
+pub mod opt;
+pub mod plru;
+pub mod random;
+pub mod rrip;
+mod sampled;
+pub mod sdbp;
+pub mod ship;
 
+use self::opt::Recording;
 use crate::{
-    cache::{Addr, Cache},
+    cache::{AccessKind, Addr, Block, Cache, Evicted},
     cpu::Cpu,
 };
 
@@ -11,11 +24,120 @@ pub trait MakeS {
     fn new(n_ways: usize) -> Self;
 }
 
+/// The access a policy is being told about
+pub struct Access {
+    pub set: usize,
+    pub tag: usize,
+    /// Address of the start of the line
+    pub line: usize,
+    pub kind: AccessKind,
+}
+
+/// One set's blocks, along with the policy's data for the set
+pub struct Set<'a, S, B: Default> {
+    pub data: &'a mut S,
+    pub blocks: &'a mut [Block<B>],
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Eviction {
+    /// Chosen by `find_victim` to make room for a fill
+    Replaced,
+    /// Removed by the hierarchy, to keep inclusion or move a line up
+    Invalidated,
+}
+
+/// A replacement policy. The cache looks lines up, fills vacant ways first
+/// and keeps the blocks' bookkeeping, telling the policy what happened
+/// through the hooks. Only the victim is the policy's to choose.
 pub trait Replace<S: MakeS, B: Default>: Sized {
-    fn access(cpu: &mut Cpu, cache: &mut Cache<S, B, Self>, addr: Addr) -> AccessResult;
+    /// Picks the way to evict from a set with no vacant way
+    fn find_victim(&mut self, cpu: &Cpu, access: &Access, set: &mut Set<S, B>) -> usize;
+
+    /// Before every access is looked up, whether or not it hits
+    fn on_access(&mut self, _cpu: &Cpu, _access: &Access, _set: &mut Set<S, B>) {}
+
+    fn on_hit(&mut self, _cpu: &Cpu, _access: &Access, _set: &mut Set<S, B>, _way: usize) {}
+
+    /// Whether a miss the cache would allocate should skip it instead. Never
+    /// asked in inclusive caches.
+    fn bypass(&mut self, _cpu: &Cpu, _access: &Access, _set: &mut Set<S, B>) -> bool {
+        false
+    }
+
+    /// Before the line in `way` leaves the set
+    fn on_evict(&mut self, _set: &mut Set<S, B>, _way: usize, _cause: Eviction) {}
+
+    /// After a line has been placed in `way`
+    fn on_fill(&mut self, _cpu: &Cpu, _access: &Access, _set: &mut Set<S, B>, _way: usize) {}
+
+    fn clear_stats(&mut self) {}
+
+    /// Anything the policy itself has to report
+    fn stats(&self) -> Option<serde_json::Value> {
+        None
+    }
+
+    /// What a policy that needs the future saw this pass
+    fn recording(&mut self) -> Option<Recording> {
+        None
+    }
+}
+
+/// How a cache hands its accesses to its policy. Every `Replace` policy gets
+/// this from the cache's own lookup, while a policy that does not keep each
+/// line to a single set implements it and takes over the whole access.
+pub trait Policy<S: MakeS, B: Default>: Sized {
+    fn access(
+        cpu: &mut Cpu,
+        cache: &mut Cache<S, B, Self>,
+        addr: Addr,
+        kind: AccessKind,
+    ) -> AccessResult;
+
+    fn invalidate(cpu: &mut Cpu, cache: &mut Cache<S, B, Self>, addr: Addr) -> Option<Evicted>;
+
+    fn clear_stats(&mut self) {}
+
+    fn stats(&self) -> Option<serde_json::Value> {
+        None
+    }
+
+    fn recording(&mut self) -> Option<Recording> {
+        None
+    }
+}
+
+impl<S: MakeS, B: Default, R: Replace<S, B>> Policy<S, B> for R {
+    fn access(
+        cpu: &mut Cpu,
+        cache: &mut Cache<S, B, Self>,
+        addr: Addr,
+        kind: AccessKind,
+    ) -> AccessResult {
+        cache.lookup(cpu, addr, kind)
+    }
+
+    fn invalidate(cpu: &mut Cpu, cache: &mut Cache<S, B, Self>, addr: Addr) -> Option<Evicted> {
+        cache.remove(cpu, addr)
+    }
+
+    fn clear_stats(&mut self) {
+        Replace::clear_stats(self)
+    }
+
+    fn stats(&self) -> Option<serde_json::Value> {
+        Replace::stats(self)
+    }
+
+    fn recording(&mut self) -> Option<Recording> {
+        Replace::recording(self)
+    }
 }
 
 pub enum AccessResult {
     Hit,
-    Miss,
+    Miss(Option<Evicted>),
+    /// A miss the policy chose not to allocate, even though the cache would
+    Bypass,
 }
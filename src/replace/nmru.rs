@@ -1,9 +1,6 @@
-use crate::{
-    cache::{Addr, Cache, IsCache},
-    cpu::Cpu,
-};
+use crate::cpu::Cpu;
 
-use super::{AccessResult, MakeS, Replace};
+use super::{Access, MakeS, Replace, Set};
 
 pub struct Nmru {
     rng: fastrand::Rng,
@@ -12,54 +9,39 @@ pub struct Nmru {
 impl Nmru {
     pub fn new() -> Self {
         Nmru {
-            rng: fastrand::Rng::new(),
+            // Seeded so runs repeat, which OPT's second pass relies on
+            rng: fastrand::Rng::with_seed(0),
         }
     }
 }
 
 impl Replace<NmruSetData, ()> for Nmru {
-    fn access(cpu: &mut Cpu, cache: &mut Cache<NmruSetData, (), Self>, addr: Addr) -> AccessResult {
-        let set_range = cache.get_set(addr.set);
-        // println!("Addr: {addr:#?}");
-        let set_slice = &mut cache.blocks[set_range];
-        // let vacant_blocks = set_slice.iter().filter(|b| !b.valid).count();
-        // First, look for a hit
-        let hit = set_slice
-            .iter_mut()
-            .enumerate()
-            .find(|(_way, b)| b.valid && b.tag == addr.tag);
+    fn find_victim(
+        &mut self,
+        _cpu: &Cpu,
+        _access: &Access,
+        set: &mut Set<NmruSetData, ()>,
+    ) -> usize {
+        let mru_way = set.data.mru_way;
+        let mut victim_way = self.rng.usize(0..(set.blocks.len() - 1));
+        if victim_way >= mru_way {
+            victim_way += 1;
+        }
+        victim_way
+    }
 
-        if let Some((way, block)) = hit {
-            cache.set_data[addr.set].mru_way = way;
-            block.read(cpu);
-            AccessResult::Hit
-        } else {
-            // if vacant_blocks < cache.n_ways {
-            //     println!("Vacant: {vacant_blocks}");
-            // }
-            // Its a miss, lets allocate space for it
-            let (way, victim) = if let Some(vacant_block) =
-                set_slice.iter_mut().enumerate().find(|(_way, b)| !b.valid)
-            {
-                // Empty Block, YAY
-                vacant_block
-            } else {
-                // No empty blocks, evict
-                let mru_way = cache.set_data[addr.set].mru_way;
-                let mut victim_way = cache.repl.rng.usize(0..(cache.n_ways - 1));
-                if victim_way >= mru_way {
-                    victim_way += 1;
-                }
-                let victim_block = &mut set_slice[victim_way];
-                victim_block.evict(cpu);
-                (victim_way, victim_block)
-            };
-            cache.set_data[addr.set].mru_way = way;
-            victim.apply(addr);
-            victim.alloc(cpu);
+    fn on_hit(&mut self, _cpu: &Cpu, _access: &Access, set: &mut Set<NmruSetData, ()>, way: usize) {
+        set.data.mru_way = way;
+    }
 
-            AccessResult::Miss
-        }
+    fn on_fill(
+        &mut self,
+        _cpu: &Cpu,
+        _access: &Access,
+        set: &mut Set<NmruSetData, ()>,
+        way: usize,
+    ) {
+        set.data.mru_way = way;
     }
 }
 
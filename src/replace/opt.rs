@@ -0,0 +1,178 @@
+use std::collections::HashMap;
+
+use serde::Serialize;
+
+use crate::cpu::Cpu;
+
+use super::{Access, MakeS, Replace, Set};
+
+/// Belady's OPT: evicts the line next used furthest in the future.
+///
+/// The future is not known until the trace has run, so OPT takes several
+/// passes. Each pass records every line the cache sees and replays what it
+/// saw in the pass before, knowing when each line is next used. Until there
+/// is such a recording, or once the stream stops matching it, LRU stands in.
+/// The stream reaching an OPT cache changes with the evictions of OPT caches
+/// above it, so passes repeat until every OPT cache replays a whole pass.
+/// Some streams never settle, such as an inclusive OPT cache's, which its own
+/// back-invalidations change, so the stats say whether the replay was exact.
+///
+/// Each pass holds two recordings, costing 20 bytes per access to the cache:
+/// the line of every access this pass and the last, and how far ahead each
+/// access of the last pass is next used.
+pub struct Opt {
+    /// Line of each access this pass
+    lines: Vec<usize>,
+    /// The last pass's lines, and how many accesses later each is next used
+    future: Option<(Vec<usize>, Vec<u32>)>,
+    /// Index of the first access that did not match the last pass
+    diverged_at: Option<usize>,
+    /// Next use of the line being accessed now, if known
+    next_use: Option<u64>,
+    pass: usize,
+}
+
+/// What an OPT cache saw in one pass
+pub struct Recording {
+    pub lines: Vec<usize>,
+    /// Accesses that matched the recording replayed before the first that did not
+    pub matched: usize,
+    /// Whether the pass matched the recording it replayed all the way through
+    pub replayed: bool,
+    pub pass: usize,
+}
+
+/// Next-use distance of a line the recording never uses again
+const NEVER: u32 = u32::MAX;
+
+#[derive(Serialize)]
+struct OptStats {
+    pass: usize,
+    /// Whether every access so far was replayed with its true next use. If
+    /// not, the misses are LRU's from `diverged_at` on and do not bound
+    /// other policies.
+    converged: bool,
+    /// Index of the first access this pass that did not match the last
+    diverged_at: Option<usize>,
+}
+
+impl Opt {
+    pub fn new(recording: Option<Recording>) -> Self {
+        let pass = recording.as_ref().map_or(1, |r| r.pass + 1);
+        let future = recording.map(|Recording { lines, .. }| {
+            let mut next_use = vec![NEVER; lines.len()];
+            let mut seen = HashMap::new();
+            for (idx, &line) in lines.iter().enumerate().rev() {
+                if let Some(next) = seen.insert(line, idx) {
+                    // Reuses too far off to count are as good as never
+                    next_use[idx] = u32::try_from(next - idx).unwrap_or(NEVER);
+                }
+            }
+            (lines, next_use)
+        });
+        Opt {
+            lines: Vec::new(),
+            future,
+            diverged_at: None,
+            next_use: None,
+            pass,
+        }
+    }
+
+    /// The next use of the line being accessed now, if known
+    fn advance(&mut self, line: usize) -> Option<u64> {
+        let idx = self.lines.len();
+        self.lines.push(line);
+        let (lines, next_use) = self.future.as_ref()?;
+        if self.diverged_at.is_some() {
+            return None;
+        }
+        if lines.get(idx) != Some(&line) {
+            self.diverged_at = Some(idx);
+            return None;
+        }
+        Some(match next_use[idx] {
+            NEVER => u64::MAX,
+            delta => (idx + delta as usize) as u64,
+        })
+    }
+}
+
+impl Replace<OptSetData, OptBlockData> for Opt {
+    fn find_victim(
+        &mut self,
+        _cpu: &Cpu,
+        _access: &Access,
+        set: &mut Set<OptSetData, OptBlockData>,
+    ) -> usize {
+        let ways = 0..set.blocks.len();
+        match self.next_use {
+            Some(_) => ways.max_by_key(|&way| set.blocks[way].repl_block.next_use),
+            None => ways.min_by_key(|&way| set.blocks[way].access_time),
+        }
+        .unwrap()
+    }
+
+    fn on_access(&mut self, _cpu: &Cpu, access: &Access, _set: &mut Set<OptSetData, OptBlockData>) {
+        self.next_use = self.advance(access.line);
+    }
+
+    fn on_hit(
+        &mut self,
+        _cpu: &Cpu,
+        _access: &Access,
+        set: &mut Set<OptSetData, OptBlockData>,
+        way: usize,
+    ) {
+        set.blocks[way].repl_block.next_use = self.next_use.unwrap_or(u64::MAX);
+    }
+
+    fn on_fill(
+        &mut self,
+        _cpu: &Cpu,
+        _access: &Access,
+        set: &mut Set<OptSetData, OptBlockData>,
+        way: usize,
+    ) {
+        set.blocks[way].repl_block.next_use = self.next_use.unwrap_or(u64::MAX);
+    }
+
+    fn stats(&self) -> Option<serde_json::Value> {
+        let stats = OptStats {
+            pass: self.pass,
+            converged: self.future.is_some() && self.diverged_at.is_none(),
+            diverged_at: self.diverged_at,
+        };
+        Some(serde_json::to_value(stats).unwrap())
+    }
+
+    fn recording(&mut self) -> Option<Recording> {
+        let matched = match &self.future {
+            Some(_) => self.diverged_at.unwrap_or(self.lines.len()),
+            None => 0,
+        };
+        let replayed = self.future.as_ref().is_some_and(|(lines, _)| {
+            self.diverged_at.is_none() && lines.len() == self.lines.len()
+        });
+        Some(Recording {
+            lines: std::mem::take(&mut self.lines),
+            matched,
+            replayed,
+            pass: self.pass,
+        })
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct OptSetData {}
+
+impl MakeS for OptSetData {
+    fn new(_n_ways: usize) -> Self {
+        OptSetData {}
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct OptBlockData {
+    next_use: u64,
+}
@@ -0,0 +1,147 @@
+use crate::cpu::Cpu;
+
+use super::{Access, MakeS, Replace, Set};
+
+/// Tree pseudo-LRU: a binary tree of bits per set, each pointing towards
+/// the half that was used less recently
+pub struct TreePlru {}
+
+impl TreePlru {
+    pub fn new() -> Self {
+        TreePlru {}
+    }
+}
+
+impl Replace<TreePlruSetData, ()> for TreePlru {
+    fn find_victim(
+        &mut self,
+        _cpu: &Cpu,
+        _access: &Access,
+        set: &mut Set<TreePlruSetData, ()>,
+    ) -> usize {
+        set.data.victim()
+    }
+
+    fn on_hit(
+        &mut self,
+        _cpu: &Cpu,
+        _access: &Access,
+        set: &mut Set<TreePlruSetData, ()>,
+        way: usize,
+    ) {
+        set.data.touch(way);
+    }
+
+    fn on_fill(
+        &mut self,
+        _cpu: &Cpu,
+        _access: &Access,
+        set: &mut Set<TreePlruSetData, ()>,
+        way: usize,
+    ) {
+        set.data.touch(way);
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct TreePlruSetData {
+    /// Node `i` of the tree is bit `i`, with children `2i` and `2i + 1` and
+    /// the root at 1. A set bit means the victim is on the right.
+    bits: u64,
+    n_ways: usize,
+}
+
+impl TreePlruSetData {
+    fn touch(&mut self, way: usize) {
+        let mut node = way + self.n_ways;
+        while node > 1 {
+            let parent = node / 2;
+            // Point away from the half just used
+            if node.is_multiple_of(2) {
+                self.bits |= 1 << parent;
+            } else {
+                self.bits &= !(1 << parent);
+            }
+            node = parent;
+        }
+    }
+
+    fn victim(&self) -> usize {
+        let mut node = 1;
+        while node < self.n_ways {
+            node = 2 * node + ((self.bits >> node) & 1) as usize;
+        }
+        node - self.n_ways
+    }
+}
+
+impl MakeS for TreePlruSetData {
+    fn new(n_ways: usize) -> Self {
+        assert!(n_ways <= 64, "Tree PLRU supports up to 64 ways");
+        TreePlruSetData { bits: 0, n_ways }
+    }
+}
+
+/// Bit pseudo-LRU: an MRU bit per way, evicting the first way whose bit is
+/// clear and clearing the others once every bit is set
+pub struct BitPlru {}
+
+impl BitPlru {
+    pub fn new() -> Self {
+        BitPlru {}
+    }
+}
+
+impl Replace<BitPlruSetData, ()> for BitPlru {
+    fn find_victim(
+        &mut self,
+        _cpu: &Cpu,
+        _access: &Access,
+        set: &mut Set<BitPlruSetData, ()>,
+    ) -> usize {
+        set.data.bits.trailing_ones() as usize
+    }
+
+    fn on_hit(
+        &mut self,
+        _cpu: &Cpu,
+        _access: &Access,
+        set: &mut Set<BitPlruSetData, ()>,
+        way: usize,
+    ) {
+        set.data.touch(way);
+    }
+
+    fn on_fill(
+        &mut self,
+        _cpu: &Cpu,
+        _access: &Access,
+        set: &mut Set<BitPlruSetData, ()>,
+        way: usize,
+    ) {
+        set.data.touch(way);
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct BitPlruSetData {
+    bits: u64,
+    n_ways: usize,
+}
+
+impl BitPlruSetData {
+    fn touch(&mut self, way: usize) {
+        self.bits |= 1 << way;
+        if self.bits.count_ones() as usize == self.n_ways {
+            // A set of one way has no other way to protect this one from
+            self.bits = if self.n_ways == 1 { 0 } else { 1 << way };
+        }
+    }
+}
+
+impl MakeS for BitPlruSetData {
+    fn new(n_ways: usize) -> Self {
+        assert!(n_ways <= 64, "Bit PLRU supports up to 64 ways");
+        BitPlruSetData { bits: 0, n_ways }
+    }
+}
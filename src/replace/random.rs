@@ -0,0 +1,36 @@
+use crate::cpu::Cpu;
+
+use super::{Access, MakeS, Replace, Set};
+
+/// Evicts a uniformly random way
+pub struct Random {
+    rng: fastrand::Rng,
+}
+
+impl Random {
+    pub fn new(seed: u64) -> Self {
+        Random {
+            rng: fastrand::Rng::with_seed(seed),
+        }
+    }
+}
+
+impl Replace<RandomSetData, ()> for Random {
+    fn find_victim(
+        &mut self,
+        _cpu: &Cpu,
+        _access: &Access,
+        set: &mut Set<RandomSetData, ()>,
+    ) -> usize {
+        self.rng.usize(0..set.blocks.len())
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct RandomSetData {}
+
+impl MakeS for RandomSetData {
+    fn new(_n_ways: usize) -> Self {
+        RandomSetData {}
+    }
+}
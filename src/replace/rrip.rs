@@ -0,0 +1,141 @@
+use crate::{
+    cache::{AccessKind, Block},
+    cpu::Cpu,
+};
+
+use super::{duel::Duel, Access, MakeS, Replace, Set};
+
+/// One BRRIP fill in this many is inserted at long rather than distant
+const BIMODAL_THROTTLE: u32 = 32;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RripMode {
+    Static,
+    Bimodal,
+    /// Set dueling between static and bimodal
+    Dynamic,
+}
+
+/// Block data holding a re-reference prediction value, which RRIP and the
+/// policies inserting with it age and evict the same way
+pub trait Rrpv {
+    fn rrpv(&mut self) -> &mut u8;
+}
+
+/// The distant re-reference value for an RRPV this many bits wide
+pub fn max_rrpv(rrpv_bits: u32) -> u8 {
+    assert!(
+        (1..=8).contains(&rrpv_bits),
+        "RRPV width must be 1 to 8 bits"
+    );
+    ((1u32 << rrpv_bits) - 1) as u8
+}
+
+/// Ages the set until some line is predicted to be re-referenced distantly,
+/// and returns the first such way
+pub fn find_distant<B: Default + Rrpv>(blocks: &mut [Block<B>], max_rrpv: u8) -> usize {
+    let oldest = blocks
+        .iter_mut()
+        .map(|b| *b.repl_block.rrpv())
+        .max()
+        .unwrap();
+    for block in blocks.iter_mut() {
+        *block.repl_block.rrpv() += max_rrpv - oldest;
+    }
+    blocks
+        .iter_mut()
+        .position(|b| *b.repl_block.rrpv() == max_rrpv)
+        .unwrap()
+}
+
+/// Re-reference interval prediction (Jaleel et al., ISCA 2010)
+pub struct Rrip {
+    mode: RripMode,
+    max_rrpv: u8,
+    rng: fastrand::Rng,
+    duel: Duel,
+}
+
+impl Rrip {
+    pub fn new(mode: RripMode, rrpv_bits: u32) -> Self {
+        Rrip {
+            mode,
+            max_rrpv: max_rrpv(rrpv_bits),
+            rng: fastrand::Rng::with_seed(0),
+            duel: Duel::new(["srrip", "brrip"]),
+        }
+    }
+
+    /// Whether a fill into this set should use BRRIP
+    fn use_bimodal(&mut self, set: usize, kind: AccessKind) -> bool {
+        match self.mode {
+            RripMode::Static => false,
+            RripMode::Bimodal => true,
+            RripMode::Dynamic => self.duel.use_second(set, kind),
+        }
+    }
+}
+
+impl Replace<RripSetData, RripBlockData> for Rrip {
+    fn find_victim(
+        &mut self,
+        _cpu: &Cpu,
+        _access: &Access,
+        set: &mut Set<RripSetData, RripBlockData>,
+    ) -> usize {
+        find_distant(set.blocks, self.max_rrpv)
+    }
+
+    fn on_hit(
+        &mut self,
+        _cpu: &Cpu,
+        _access: &Access,
+        set: &mut Set<RripSetData, RripBlockData>,
+        way: usize,
+    ) {
+        set.blocks[way].repl_block.rrpv = 0;
+    }
+
+    fn on_fill(
+        &mut self,
+        _cpu: &Cpu,
+        access: &Access,
+        set: &mut Set<RripSetData, RripBlockData>,
+        way: usize,
+    ) {
+        let bimodal = self.use_bimodal(access.set, access.kind);
+        set.blocks[way].repl_block.rrpv = if bimodal && self.rng.u32(0..BIMODAL_THROTTLE) != 0 {
+            self.max_rrpv
+        } else {
+            self.max_rrpv - 1
+        };
+    }
+
+    fn clear_stats(&mut self) {
+        self.duel.clear_stats();
+    }
+
+    fn stats(&self) -> Option<serde_json::Value> {
+        (self.mode == RripMode::Dynamic).then(|| self.duel.stats())
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct RripSetData {}
+
+impl MakeS for RripSetData {
+    fn new(_n_ways: usize) -> Self {
+        RripSetData {}
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct RripBlockData {
+    rrpv: u8,
+}
+
+impl Rrpv for RripBlockData {
+    fn rrpv(&mut self) -> &mut u8 {
+        &mut self.rrpv
+    }
+}
@@ -0,0 +1,34 @@
+use crate::cache::AccessKind;
+
+/// Whether an access can train or consult a PC-indexed predictor. Only demand
+/// accesses have a PC worth predicting from, as writebacks, victims and
+/// prefetches carry whichever instruction happened to cause them.
+pub fn has_pc(kind: AccessKind) -> bool {
+    kind.is_demand()
+}
+
+/// State kept for a few evenly spaced sets, which policies learn from rather
+/// than watching every set
+pub struct SampledSets<T> {
+    period: usize,
+    sets: Vec<T>,
+}
+
+impl<T> SampledSets<T> {
+    /// Samples roughly `n_sampled` of the cache's `n_sets` sets
+    pub fn new(n_sets: usize, n_sampled: usize, make: impl FnMut() -> T) -> Self {
+        let period = (n_sets / n_sampled).max(1);
+        SampledSets {
+            period,
+            sets: std::iter::repeat_with(make).take(n_sets / period).collect(),
+        }
+    }
+
+    /// The state for `set`, if it is sampled
+    pub fn get(&mut self, set: usize) -> Option<&mut T> {
+        if !set.is_multiple_of(self.period) {
+            return None;
+        }
+        self.sets.get_mut(set / self.period)
+    }
+}
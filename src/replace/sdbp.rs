@@ -0,0 +1,289 @@
+use serde::Serialize;
+
+use crate::cpu::Cpu;
+
+use super::{
+    sampled::{self, SampledSets},
+    Access, Eviction, MakeS, Replace, Set,
+};
+
+type PredCounter = u8;
+const PRED_MAX: PredCounter = 3;
+const TABLE_BITS: u32 = 12; // 4K counters per table
+/// Each table hashes the signature with its own odd multiplier, so two
+/// signatures colliding in one table rarely collide in the others
+const TABLE_SKEWS: [u64; 3] = [0x9e37_79b9, 0x85eb_ca6b, 0xc2b2_ae35];
+/// Lines whose counters sum to at least this are predicted dead
+const DEAD_THRESHOLD: u32 = 8;
+const SIGNATURE_BITS: u32 = 15;
+const PARTIAL_TAG_BITS: u32 = 15;
+/// Sets whose sampler trains the predictor
+const SAMPLED_SETS: usize = 32;
+
+/// How well a dead block predictor did, judged when lines leave or are reused
+#[derive(Debug, Default, Serialize)]
+pub struct DeadBlockStats {
+    /// Lines evicted while predicted dead
+    correct: u64,
+    /// Lines hit while predicted dead
+    wrong: u64,
+    /// Lines evicted without being predicted dead
+    missed: u64,
+    accuracy: f64,
+    coverage: f64,
+}
+
+impl DeadBlockStats {
+    pub fn evicted(&mut self, predicted_dead: bool) {
+        if predicted_dead {
+            self.correct += 1;
+        } else {
+            self.missed += 1;
+        }
+    }
+
+    pub fn hit(&mut self, predicted_dead: bool) {
+        self.wrong += predicted_dead as u64;
+    }
+
+    pub fn make_stats(&self) -> serde_json::Value {
+        let stats = DeadBlockStats {
+            accuracy: self.correct as f64 / (self.correct + self.wrong) as f64,
+            coverage: self.correct as f64 / (self.correct + self.missed) as f64,
+            ..*self
+        };
+        serde_json::to_value(stats).unwrap()
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+struct SamplerEntry {
+    valid: bool,
+    partial_tag: u16,
+    /// Signature of the last access to the line
+    signature: u16,
+    /// Sampler accesses to the set when the line was last touched
+    last_use: u64,
+}
+
+/// Tags of a sampled set, kept apart from the cache so the sampler can be
+/// smaller and run its own LRU
+struct SamplerSet {
+    entries: Vec<SamplerEntry>,
+    accesses: u64,
+}
+
+#[derive(Serialize)]
+struct SdbpStats {
+    fills: u64,
+    bypasses: u64,
+    /// Victims chosen because they were predicted dead rather than for age
+    dead_victims: u64,
+    #[serde(flatten)]
+    prediction: serde_json::Value,
+}
+
+/// Sampling dead block prediction (Khan et al., MICRO 2010). Only a few
+/// sets train the predictor, through a sampler of partial tags, and three
+/// skewed tables indexed by the PC of the last access vote on whether a
+/// line is dead. Dead lines are evicted first, and with bypass on, a miss
+/// predicted dead on arrival is not allocated.
+pub struct Sdbp {
+    tables: [Vec<PredCounter>; 3],
+    sampler: SampledSets<SamplerSet>,
+    bypass: bool,
+    /// Whether the access being handled is predicted to leave its line dead
+    predicted_dead: bool,
+    fills: u64,
+    bypasses: u64,
+    dead_victims: u64,
+    prediction: DeadBlockStats,
+}
+
+impl Sdbp {
+    pub fn new(n_sets: usize, n_ways: usize, bypass: bool) -> Self {
+        Sdbp {
+            tables: std::array::from_fn(|_| vec![0; 1 << TABLE_BITS]),
+            sampler: SampledSets::new(n_sets, SAMPLED_SETS, || SamplerSet {
+                entries: vec![SamplerEntry::default(); n_ways],
+                accesses: 0,
+            }),
+            bypass,
+            predicted_dead: false,
+            fills: 0,
+            bypasses: 0,
+            dead_victims: 0,
+            prediction: DeadBlockStats::default(),
+        }
+    }
+
+    fn signature(ip: u64) -> u16 {
+        let mask = (1u64 << SIGNATURE_BITS) - 1;
+        ((ip ^ (ip >> SIGNATURE_BITS) ^ (ip >> (2 * SIGNATURE_BITS))) & mask) as u16
+    }
+
+    fn index(signature: u16, table: usize) -> usize {
+        let hash = (signature as u64).wrapping_mul(TABLE_SKEWS[table]);
+        ((hash >> SIGNATURE_BITS) & ((1 << TABLE_BITS) - 1)) as usize
+    }
+
+    fn predict_dead(&self, signature: u16) -> bool {
+        let votes: u32 = (0..self.tables.len())
+            .map(|table| self.tables[table][Sdbp::index(signature, table)] as u32)
+            .sum();
+        votes >= DEAD_THRESHOLD
+    }
+
+    fn train(&mut self, signature: u16, dead: bool) {
+        for (table, counters) in self.tables.iter_mut().enumerate() {
+            let counter = &mut counters[Sdbp::index(signature, table)];
+            if dead {
+                *counter = (*counter + 1).min(PRED_MAX);
+            } else {
+                *counter = counter.saturating_sub(1);
+            }
+        }
+    }
+
+    /// Lets the sampler see a demand access if its set is sampled. A line
+    /// touched again proves its last signature live, and one falling out of
+    /// the sampler proves its last signature dead.
+    fn sample(&mut self, set: usize, tag: usize, signature: u16) {
+        let Some(sampler_set) = self.sampler.get(set) else {
+            return;
+        };
+        let now = sampler_set.accesses;
+        sampler_set.accesses += 1;
+        let partial_tag = (tag & ((1 << PARTIAL_TAG_BITS) - 1)) as u16;
+        let entries = &mut sampler_set.entries;
+
+        let (way, trained) = match entries
+            .iter()
+            .position(|e| e.valid && e.partial_tag == partial_tag)
+        {
+            Some(way) => (way, Some((entries[way].signature, false))),
+            None => match entries.iter().position(|e| !e.valid) {
+                Some(way) => (way, None),
+                None => {
+                    let way = (0..entries.len())
+                        .min_by_key(|&way| entries[way].last_use)
+                        .unwrap();
+                    (way, Some((entries[way].signature, true)))
+                }
+            },
+        };
+        entries[way] = SamplerEntry {
+            valid: true,
+            partial_tag,
+            signature,
+            last_use: now,
+        };
+        if let Some((last_signature, dead)) = trained {
+            self.train(last_signature, dead);
+        }
+    }
+}
+
+impl Replace<SdbpSetData, SdbpBlockData> for Sdbp {
+    fn find_victim(
+        &mut self,
+        _cpu: &Cpu,
+        _access: &Access,
+        set: &mut Set<SdbpSetData, SdbpBlockData>,
+    ) -> usize {
+        // Lines predicted dead go first, otherwise the least recently used
+        if let Some(way) = set.blocks.iter().position(|b| b.repl_block.dead) {
+            self.dead_victims += 1;
+            return way;
+        }
+        (0..set.blocks.len())
+            .min_by_key(|&way| set.blocks[way].access_time)
+            .unwrap()
+    }
+
+    fn on_access(
+        &mut self,
+        cpu: &Cpu,
+        access: &Access,
+        _set: &mut Set<SdbpSetData, SdbpBlockData>,
+    ) {
+        self.predicted_dead = sampled::has_pc(access.kind) && {
+            let signature = Sdbp::signature(cpu.ip);
+            self.sample(access.set, access.tag, signature);
+            self.predict_dead(signature)
+        };
+    }
+
+    fn on_hit(
+        &mut self,
+        _cpu: &Cpu,
+        access: &Access,
+        set: &mut Set<SdbpSetData, SdbpBlockData>,
+        way: usize,
+    ) {
+        if sampled::has_pc(access.kind) {
+            let data = &mut set.blocks[way].repl_block;
+            self.prediction.hit(data.dead);
+            data.dead = self.predicted_dead;
+        }
+    }
+
+    fn bypass(
+        &mut self,
+        _cpu: &Cpu,
+        _access: &Access,
+        _set: &mut Set<SdbpSetData, SdbpBlockData>,
+    ) -> bool {
+        let bypass = self.bypass && self.predicted_dead;
+        self.bypasses += bypass as u64;
+        bypass
+    }
+
+    fn on_evict(&mut self, set: &mut Set<SdbpSetData, SdbpBlockData>, way: usize, cause: Eviction) {
+        if cause == Eviction::Replaced {
+            self.prediction.evicted(set.blocks[way].repl_block.dead);
+        }
+    }
+
+    fn on_fill(
+        &mut self,
+        _cpu: &Cpu,
+        _access: &Access,
+        set: &mut Set<SdbpSetData, SdbpBlockData>,
+        way: usize,
+    ) {
+        set.blocks[way].repl_block.dead = self.predicted_dead;
+        self.fills += 1;
+    }
+
+    fn clear_stats(&mut self) {
+        self.fills = 0;
+        self.bypasses = 0;
+        self.dead_victims = 0;
+        self.prediction = DeadBlockStats::default();
+    }
+
+    fn stats(&self) -> Option<serde_json::Value> {
+        let stats = SdbpStats {
+            fills: self.fills,
+            bypasses: self.bypasses,
+            dead_victims: self.dead_victims,
+            prediction: self.prediction.make_stats(),
+        };
+        Some(serde_json::to_value(stats).unwrap())
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct SdbpSetData {}
+
+impl MakeS for SdbpSetData {
+    fn new(_n_ways: usize) -> Self {
+        SdbpSetData {}
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct SdbpBlockData {
+    dead: bool,
+}
@@ -0,0 +1,197 @@
+use serde::Serialize;
+
+use crate::cpu::Cpu;
+
+use super::{
+    rrip::{self, Rrpv},
+    sampled, Access, Eviction, MakeS, Replace, Set,
+};
+
+type ShctCounter = u8;
+const SHCT_MAX: ShctCounter = 7;
+/// Memory region signatures group addresses by 16KB
+const REGION_SHIFT: u32 = 14;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Signature {
+    /// The instruction that filled the line
+    Pc,
+    /// The region of memory the line is in
+    Memory,
+    /// Which of the instructions leading up to the fill touched memory
+    InstrSequence,
+}
+
+#[derive(Serialize)]
+struct ShipStats {
+    fills: u64,
+    distant_fills: u64,
+    /// Evicted lines whose reuse the SHCT predicted correctly
+    predictions: u64,
+    correct: u64,
+    accuracy: f64,
+}
+
+/// Signature-based hit prediction over SRRIP (Wu et al., MICRO 2011)
+pub struct Ship {
+    signature: Signature,
+    shct: Vec<ShctCounter>,
+    shct_bits: u32,
+    max_rrpv: u8,
+    fills: u64,
+    distant_fills: u64,
+    predictions: u64,
+    correct: u64,
+}
+
+impl Ship {
+    pub fn new(signature: Signature, shct_bits: u32, rrpv_bits: u32) -> Self {
+        assert!(
+            (1..=24).contains(&shct_bits),
+            "SHCT must have 2 to 16M entries"
+        );
+        Ship {
+            signature,
+            // Counters start weakly reused, so lines are not distant until shown dead
+            shct: vec![1; 1 << shct_bits],
+            shct_bits,
+            max_rrpv: rrip::max_rrpv(rrpv_bits),
+            fills: 0,
+            distant_fills: 0,
+            predictions: 0,
+            correct: 0,
+        }
+    }
+
+    fn signature(&self, cpu: &Cpu, line_addr: usize) -> u32 {
+        let value = match self.signature {
+            Signature::Pc => cpu.ip,
+            Signature::Memory => (line_addr >> REGION_SHIFT) as u64,
+            Signature::InstrSequence => cpu.iseq,
+        };
+        let mask = (1u64 << self.shct_bits) - 1;
+        ((value ^ (value >> self.shct_bits) ^ (value >> (2 * self.shct_bits))) & mask) as u32
+    }
+
+    /// Whether `access` has a signature, which instruction-based ones only
+    /// do for demand accesses
+    fn has_signature(&self, access: &Access) -> bool {
+        self.signature == Signature::Memory || sampled::has_pc(access.kind)
+    }
+}
+
+impl Replace<ShipSetData, ShipBlockData> for Ship {
+    fn find_victim(
+        &mut self,
+        _cpu: &Cpu,
+        _access: &Access,
+        set: &mut Set<ShipSetData, ShipBlockData>,
+    ) -> usize {
+        rrip::find_distant(set.blocks, self.max_rrpv)
+    }
+
+    fn on_hit(
+        &mut self,
+        _cpu: &Cpu,
+        access: &Access,
+        set: &mut Set<ShipSetData, ShipBlockData>,
+        way: usize,
+    ) {
+        let data = &mut set.blocks[way].repl_block;
+        data.rrpv = 0;
+        if data.predicted && self.has_signature(access) {
+            data.reused = true;
+            let counter = &mut self.shct[data.signature as usize];
+            *counter = (*counter + 1).min(SHCT_MAX);
+        }
+    }
+
+    fn on_evict(&mut self, set: &mut Set<ShipSetData, ShipBlockData>, way: usize, cause: Eviction) {
+        let data = &set.blocks[way].repl_block;
+        if cause != Eviction::Replaced || !data.predicted {
+            return;
+        }
+        // A line leaving without reuse trains its signature towards dead
+        if !data.reused {
+            let counter = &mut self.shct[data.signature as usize];
+            *counter = counter.saturating_sub(1);
+        }
+        self.predictions += 1;
+        self.correct += (data.predicted_dead != data.reused) as u64;
+    }
+
+    fn on_fill(
+        &mut self,
+        cpu: &Cpu,
+        access: &Access,
+        set: &mut Set<ShipSetData, ShipBlockData>,
+        way: usize,
+    ) {
+        self.fills += 1;
+        if !self.has_signature(access) {
+            // Nothing to predict from, so the line goes in as SRRIP would put it
+            set.blocks[way].repl_block = ShipBlockData {
+                rrpv: self.max_rrpv - 1,
+                ..Default::default()
+            };
+            return;
+        }
+        let signature = self.signature(cpu, access.line);
+        let predicted_dead = self.shct[signature as usize] == 0;
+        self.distant_fills += predicted_dead as u64;
+        set.blocks[way].repl_block = ShipBlockData {
+            rrpv: if predicted_dead {
+                self.max_rrpv
+            } else {
+                self.max_rrpv - 1
+            },
+            signature,
+            predicted: true,
+            reused: false,
+            predicted_dead,
+        };
+    }
+
+    fn clear_stats(&mut self) {
+        self.fills = 0;
+        self.distant_fills = 0;
+        self.predictions = 0;
+        self.correct = 0;
+    }
+
+    fn stats(&self) -> Option<serde_json::Value> {
+        let stats = ShipStats {
+            fills: self.fills,
+            distant_fills: self.distant_fills,
+            predictions: self.predictions,
+            correct: self.correct,
+            accuracy: self.correct as f64 / self.predictions as f64,
+        };
+        Some(serde_json::to_value(stats).unwrap())
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct ShipSetData {}
+
+impl MakeS for ShipSetData {
+    fn new(_n_ways: usize) -> Self {
+        ShipSetData {}
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct ShipBlockData {
+    rrpv: u8,
+    signature: u32,
+    /// Whether the fill had a signature to predict from and train
+    predicted: bool,
+    reused: bool,
+    predicted_dead: bool,
+}
+
+impl Rrpv for ShipBlockData {
+    fn rrpv(&mut self) -> &mut u8 {
+        &mut self.rrpv
+    }
+}
@@ -0,0 +1,107 @@
+use std::{fs, path::Path, str::FromStr};
+
+/// A representative slice of a trace
+#[derive(Debug, Clone, Copy)]
+pub struct Region {
+    /// Records into the trace the measured instructions start at
+    pub start: u64,
+    /// Share of the whole trace the region stands for, summing to 1
+    pub weight: f64,
+    /// Instructions to warm up on and to measure, if not the run's own
+    pub warmup: Option<u64>,
+    pub length: Option<u64>,
+}
+
+/// Whether each region starts from empty caches or from where the one before
+/// left them
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RegionState {
+    Fresh,
+    Carry,
+}
+
+impl FromStr for RegionState {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "fresh" => Ok(RegionState::Fresh),
+            "carry" => Ok(RegionState::Carry),
+            _ => Err(format!("Unrecognized region state: {}", s)),
+        }
+    }
+}
+
+/// Reads a file with a `<start> <weight> [<warmup> [<length>]]` line per region,
+/// skipping blank lines and `#` comments. Regions come back in trace order,
+/// with their weights scaled to sum to 1.
+pub fn read(path: &Path) -> Vec<Region> {
+    let text = fs::read_to_string(path)
+        .unwrap_or_else(|err| panic!("Could not read simpoints {}: {}", path.display(), err));
+    let mut regions: Vec<Region> = text
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty() && !line.trim_start().starts_with('#'))
+        .map(|(idx, line)| {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            let (start, weight, lengths) = match fields[..] {
+                [start, weight, ref lengths @ ..] if lengths.len() <= 2 => (start, weight, lengths),
+                _ => panic!(
+                    "Simpoints line {} should be <start> <weight> [<warmup> [<length>]], not {:?}",
+                    idx + 1,
+                    line
+                ),
+            };
+            let integer = |name: &str, field: &str| -> u64 {
+                field.parse().unwrap_or_else(|_| {
+                    panic!(
+                        "Simpoints line {}: {} {:?} is not an integer",
+                        idx + 1,
+                        name,
+                        field
+                    )
+                })
+            };
+            let start = integer("start", start);
+            let weight: f64 = weight.parse().unwrap_or_else(|_| {
+                panic!(
+                    "Simpoints line {}: weight {:?} is not a number",
+                    idx + 1,
+                    weight
+                )
+            });
+            assert!(
+                weight.is_finite() && weight >= 0.0,
+                "Simpoints line {}: weight {} should not be negative",
+                idx + 1,
+                weight
+            );
+            Region {
+                start,
+                weight,
+                warmup: lengths.first().map(|warmup| integer("warmup", warmup)),
+                length: lengths.get(1).map(|length| integer("length", length)),
+            }
+        })
+        .collect();
+    assert!(
+        !regions.is_empty(),
+        "Simpoints {} has no regions",
+        path.display()
+    );
+
+    let total: f64 = regions.iter().map(|r| r.weight).sum();
+    assert!(total > 0.0, "Simpoints {} has no weight", path.display());
+    regions.iter_mut().for_each(|r| r.weight /= total);
+    regions.sort_by_key(|r| r.start);
+    regions
+}
+
+/// `count` summed over regions' stats, each scaled by how much of the trace
+/// it stands for
+pub fn sum<T>(regions: &[(f64, &T)], count: impl Fn(&T) -> f64) -> f64 {
+    regions
+        .iter()
+        .map(|&(scale, stats)| scale * count(stats))
+        .sum()
+}
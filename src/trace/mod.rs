@@ -0,0 +1,450 @@
+use std::{
+    error, ffi, fmt, fs,
+    io::{self, BorrowedBuf, BufReader, ErrorKind, Read, Seek},
+    mem::MaybeUninit,
+    path::{Path, PathBuf},
+    str::FromStr,
+    thread::{self, JoinHandle},
+};
+
+use crossbeam::channel::{Receiver, Sender};
+use flate2::read::MultiGzDecoder;
+use xz2::read::XzDecoder;
+
+use crate::cache::AccessKind;
+
+mod xz_index;
+
+// Instruction Format
+const NUM_INSTR_DESTINATIONS: usize = 2;
+const NUM_INSTR_SOURCES: usize = 4;
+const INSTR_SIZE: usize = std::mem::size_of::<Instr>();
+
+#[repr(C)]
+#[derive(Default, Clone, Copy, Debug)]
+pub struct Instr {
+    pub ip: ffi::c_ulonglong,
+
+    pub is_branch: ffi::c_uchar,
+    pub branch_taken: ffi::c_uchar,
+
+    pub destination_registers: [ffi::c_uchar; NUM_INSTR_DESTINATIONS],
+    pub source_registers: [ffi::c_uchar; NUM_INSTR_SOURCES],
+
+    pub destination_memory: [ffi::c_ulonglong; NUM_INSTR_DESTINATIONS],
+    pub source_memory: [ffi::c_ulonglong; NUM_INSTR_SOURCES],
+}
+
+impl Instr {
+    pub fn data_addresses<'a>(&'a self) -> impl Iterator<Item = (usize, AccessKind)> + 'a {
+        self.source_memory
+            .iter()
+            .map(|&addr| addr as usize)
+            .filter(|&addr| addr != 0)
+            .map(|addr| (addr, AccessKind::Load))
+            .chain(
+                self.destination_memory
+                    .iter()
+                    .map(|&addr| addr as usize)
+                    .filter(|&addr| addr != 0)
+                    .map(|addr| (addr, AccessKind::Store)),
+            )
+    }
+}
+
+/// How a trace file is compressed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Xz,
+    Gzip,
+    Zstd,
+    /// Records stored as they are
+    Raw,
+}
+
+impl Format {
+    const MAGIC: [(Format, &'static [u8]); 3] = [
+        (Format::Xz, &[0xfd, b'7', b'z', b'X', b'Z', 0x00]),
+        (Format::Gzip, &[0x1f, 0x8b]),
+        (Format::Zstd, &[0x28, 0xb5, 0x2f, 0xfd]),
+    ];
+
+    fn name(self) -> &'static str {
+        match self {
+            Format::Xz => "xz",
+            Format::Gzip => "gzip",
+            Format::Zstd => "zstd",
+            Format::Raw => "raw",
+        }
+    }
+
+    fn from_extension(path: &Path) -> Format {
+        match path.extension().and_then(|ext| ext.to_str()) {
+            Some("xz") => Format::Xz,
+            Some("gz") => Format::Gzip,
+            Some("zst") => Format::Zstd,
+            _ => Format::Raw,
+        }
+    }
+
+    /// Reads the format from the start of the file. Raw traces have no magic
+    /// bytes, so a file without any is raw unless its extension says it is
+    /// compressed, in which case it is not a trace we can read.
+    fn detect(stream: &mut fs::File, path: &Path) -> io::Result<Format> {
+        let mut start = Vec::new();
+        stream.by_ref().take(6).read_to_end(&mut start)?;
+        stream.seek(io::SeekFrom::Start(0))?;
+        if let Some(&(format, _)) = Format::MAGIC
+            .iter()
+            .find(|(_, magic)| start.starts_with(magic))
+        {
+            return Ok(format);
+        }
+        match Format::from_extension(path) {
+            Format::Raw => Ok(Format::Raw),
+            named => Err(io::Error::new(
+                ErrorKind::InvalidData,
+                format!(
+                    "has a .{} extension but no {} header",
+                    path.extension().unwrap().to_string_lossy(),
+                    named.name()
+                ),
+            )),
+        }
+    }
+
+    fn decoder(self, stream: impl Read + Send + 'static) -> io::Result<Box<dyn Read + Send>> {
+        Ok(match self {
+            Format::Xz => Box::new(XzDecoder::new(stream)),
+            Format::Gzip => Box::new(MultiGzDecoder::new(stream)),
+            Format::Zstd => Box::new(zstd::Decoder::new(stream)?),
+            Format::Raw => Box::new(BufReader::new(stream)),
+        })
+    }
+
+    /// A decoder reading the file from where it is now
+    fn open(self, file: &fs::File) -> io::Result<Box<dyn Read + Send>> {
+        self.decoder(file.try_clone()?)
+    }
+}
+
+/// What to do when a trace runs out before the run is over
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TraceEnd {
+    /// Stop the core, reporting what it ran
+    Stop,
+    /// Start the trace again from the beginning
+    Wrap,
+    /// Abort the simulation
+    Error,
+}
+
+impl FromStr for TraceEnd {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "stop" => Ok(TraceEnd::Stop),
+            "wrap" => Ok(TraceEnd::Wrap),
+            "error" => Ok(TraceEnd::Error),
+            _ => Err(format!("Unrecognized trace end: {}", s)),
+        }
+    }
+}
+
+/// What to do with a record that is cut off or cannot be decoded
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BadRecord {
+    /// Abort the simulation
+    Error,
+    /// Drop the rest of the trace and carry on as if it ended cleanly
+    Skip,
+    /// Stop the core at the last complete record
+    Stop,
+}
+
+impl FromStr for BadRecord {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "error" => Ok(BadRecord::Error),
+            "skip" => Ok(BadRecord::Skip),
+            "stop" => Ok(BadRecord::Stop),
+            _ => Err(format!("Unrecognized bad record handling: {}", s)),
+        }
+    }
+}
+
+/// A record that could not be read. Offsets are into the decompressed trace.
+#[derive(Debug)]
+pub enum TraceError {
+    /// The trace ends partway through a record
+    Truncated {
+        record: u64,
+        offset: u64,
+        bytes: usize,
+    },
+    /// The file could not be read or decompressed
+    Io {
+        record: u64,
+        offset: u64,
+        source: io::Error,
+    },
+    /// The trace ran out before the records asked to be skipped
+    TooShort { records: u64 },
+}
+
+impl fmt::Display for TraceError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TraceError::Truncated {
+                record,
+                offset,
+                bytes,
+            } => write!(
+                f,
+                "record {} at byte {} is cut off after {} of its {} bytes",
+                record, offset, bytes, INSTR_SIZE
+            ),
+            TraceError::Io {
+                record,
+                offset,
+                source,
+            } => write!(
+                f,
+                "could not read record {} at byte {}: {}",
+                record, offset, source
+            ),
+            TraceError::TooShort { records } => write!(
+                f,
+                "only {} records, ending at byte {}, fewer than were skipped",
+                records,
+                records * INSTR_SIZE as u64
+            ),
+        }
+    }
+}
+
+impl error::Error for TraceError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            TraceError::Truncated { .. } | TraceError::TooShort { .. } => None,
+            TraceError::Io { source, .. } => Some(source),
+        }
+    }
+}
+
+/// What the reader thread sends. The channel closes once the trace has ended
+/// for good.
+pub enum Chunk {
+    Instrs(Vec<Instr>),
+    /// The trace ran out and started again from the beginning
+    Wrapped,
+    /// Records before this one were complete, and the thread carries on past
+    /// it only when skipping bad records
+    Error(TraceError),
+    /// The trace could not be read as far as the records to skip, so nothing
+    /// follows
+    SkipFailed(TraceError),
+}
+
+pub struct Trace {
+    pub path: PathBuf,
+    pub rec: Receiver<Chunk>,
+    _thread: JoinHandle<()>,
+}
+
+impl Trace {
+    /// The first `skip` records are never sent. When the trace wraps, it
+    /// starts over from the beginning.
+    pub fn read(
+        path: PathBuf,
+        skip: u64,
+        end: TraceEnd,
+        bad_record: BadRecord,
+        instr_per_block: usize,
+        blocks_per_queue: usize,
+    ) -> io::Result<Trace> {
+        let mut stream = fs::File::open(path.clone())?;
+        let format = Format::detect(&mut stream, &path)?;
+        let (sender, receiver) = crossbeam::channel::bounded(blocks_per_queue);
+
+        let wrap = end == TraceEnd::Wrap;
+        let skip_errors = bad_record == BadRecord::Skip;
+        let t = thread::spawn(move || {
+            Trace::run_thread(
+                stream,
+                format,
+                skip,
+                wrap,
+                skip_errors,
+                instr_per_block,
+                sender,
+            )
+        });
+
+        Ok(Trace {
+            path,
+            rec: receiver,
+            _thread: t,
+        })
+    }
+
+    /// A decoder positioned after the first `skip` records. Raw traces seek
+    /// straight there, and xz traces to the block holding it if the file is
+    /// indexed. Everything else is decoded and thrown away.
+    fn fast_forward(
+        stream: &mut fs::File,
+        format: Format,
+        skip: u64,
+    ) -> Result<Box<dyn Read + Send>, TraceError> {
+        let target = skip * INSTR_SIZE as u64;
+        let io_error = |position: u64, source| TraceError::Io {
+            record: position / INSTR_SIZE as u64,
+            offset: position / INSTR_SIZE as u64 * INSTR_SIZE as u64,
+            source,
+        };
+        let started = match format {
+            _ if skip == 0 => None,
+            Format::Raw => {
+                let len = stream.metadata().map_err(|err| io_error(0, err))?.len();
+                let start = target.min(len);
+                stream
+                    .seek(io::SeekFrom::Start(start))
+                    .map_err(|err| io_error(0, err))?;
+                let decoder = format.open(stream).map_err(|err| io_error(start, err))?;
+                Some((decoder, start))
+            }
+            Format::Xz => match xz_index::seek(stream, target).map_err(|err| io_error(0, err))? {
+                Some((indexed, start)) => {
+                    let decoder = format
+                        .decoder(indexed)
+                        .map_err(|err| io_error(start, err))?;
+                    Some((decoder, start))
+                }
+                None => None,
+            },
+            Format::Gzip | Format::Zstd => None,
+        };
+        let (mut decoder, mut position) = match started {
+            Some(started) => started,
+            None => {
+                // Looking for an index may have moved the file
+                stream
+                    .seek(io::SeekFrom::Start(0))
+                    .map_err(|err| io_error(0, err))?;
+                (format.open(stream).map_err(|err| io_error(0, err))?, 0)
+            }
+        };
+
+        let mut discard = vec![0; 1 << 16];
+        while position < target {
+            let want = ((target - position) as usize).min(discard.len());
+            match decoder.read(&mut discard[..want]) {
+                Ok(0) => {
+                    return Err(TraceError::TooShort {
+                        records: position / INSTR_SIZE as u64,
+                    })
+                }
+                Ok(read) => position += read as u64,
+                Err(err) if err.kind() == ErrorKind::Interrupted => {}
+                Err(err) => return Err(io_error(position, err)),
+            }
+        }
+        Ok(decoder)
+    }
+
+    fn run_thread(
+        mut stream: fs::File,
+        format: Format,
+        skip: u64,
+        wrap: bool,
+        skip_errors: bool,
+        instr_per_block: usize,
+        queue: Sender<Chunk>,
+    ) {
+        let mut decoder = match Trace::fast_forward(&mut stream, format, skip) {
+            Ok(decoder) => decoder,
+            Err(error) => {
+                let _ = queue.send(Chunk::SkipFailed(error));
+                return;
+            }
+        };
+        // Index of the next record, counting from the beginning of the trace
+        let mut records = skip;
+        loop {
+            let mut error = None;
+            while error.is_none() {
+                let mut buffer = Vec::<Instr>::with_capacity(instr_per_block);
+                let (num_bytes_written, read_error) = {
+                    let (head, byte_buffer, tail) = unsafe {
+                        buffer
+                            .spare_capacity_mut()
+                            .align_to_mut::<MaybeUninit<u8>>()
+                    };
+                    assert_eq!(head.len(), 0);
+                    assert_eq!(tail.len(), 0);
+                    let mut borrowed: BorrowedBuf<'_> = byte_buffer.into();
+                    let read_error = match decoder.read_buf_exact(borrowed.unfilled()) {
+                        Ok(()) => None,
+                        Err(err) if err.kind() == ErrorKind::UnexpectedEof => None,
+                        Err(err) => Some(err),
+                    };
+                    (borrowed.init_len(), read_error)
+                };
+                let complete = num_bytes_written / INSTR_SIZE;
+                unsafe { buffer.set_len(complete) };
+                records += complete as u64;
+
+                let offset = records * INSTR_SIZE as u64;
+                let partial = num_bytes_written % INSTR_SIZE;
+                error = match read_error {
+                    Some(source) => Some(TraceError::Io {
+                        record: records,
+                        offset,
+                        source,
+                    }),
+                    None if partial != 0 => Some(TraceError::Truncated {
+                        record: records,
+                        offset,
+                        bytes: partial,
+                    }),
+                    None => None,
+                };
+                if num_bytes_written == 0 && error.is_none() {
+                    break;
+                }
+                if complete > 0 && queue.send(Chunk::Instrs(buffer)).is_err() {
+                    return;
+                }
+            }
+
+            if let Some(error) = error {
+                if queue.send(Chunk::Error(error)).is_err() || !skip_errors {
+                    return;
+                }
+            }
+            // An empty trace would wrap forever without sending anything
+            if !wrap || records == 0 || queue.send(Chunk::Wrapped).is_err() {
+                return;
+            }
+            records = 0;
+            let restarted = stream
+                .seek(io::SeekFrom::Start(0))
+                .and_then(|_| format.open(&stream));
+            decoder = match restarted {
+                Ok(decoder) => decoder,
+                Err(source) => {
+                    let error = TraceError::Io {
+                        record: 0,
+                        offset: 0,
+                        source,
+                    };
+                    let _ = queue.send(Chunk::Error(error));
+                    return;
+                }
+            };
+        }
+    }
+}
@@ -0,0 +1,151 @@
+use std::{
+    fs,
+    io::{self, Cursor, Read, Seek, SeekFrom},
+};
+
+use flate2::Crc;
+
+const HEADER_SIZE: u64 = 12;
+const FOOTER_SIZE: u64 = 12;
+const FOOTER_MAGIC: &[u8] = b"YZ";
+
+struct Block {
+    /// Compressed size without the padding that follows the block
+    unpadded: u64,
+    uncompressed: u64,
+}
+
+impl Block {
+    fn padded(&self) -> u64 {
+        self.unpadded.next_multiple_of(4)
+    }
+}
+
+/// xz's variable length integers: 7 bits a byte, least significant first
+fn read_varint(bytes: &mut impl Iterator<Item = u8>) -> Option<u64> {
+    let mut value = 0;
+    for shift in (0..63).step_by(7) {
+        let byte = bytes.next()?;
+        value |= ((byte & 0x7f) as u64) << shift;
+        if byte & 0x80 == 0 {
+            return Some(value);
+        }
+    }
+    None
+}
+
+fn write_varint(out: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        out.push((value as u8) | 0x80);
+        value >>= 7;
+    }
+    out.push(value as u8);
+}
+
+fn crc32(bytes: &[u8]) -> [u8; 4] {
+    let mut crc = Crc::new();
+    crc.update(bytes);
+    crc.sum().to_le_bytes()
+}
+
+/// The blocks of a file holding a single xz stream, None if it holds
+/// anything else
+fn read_index(file: &mut fs::File) -> io::Result<Option<Vec<Block>>> {
+    let len = file.metadata()?.len();
+    if len < HEADER_SIZE + FOOTER_SIZE {
+        return Ok(None);
+    }
+    let mut footer = [0; FOOTER_SIZE as usize];
+    file.seek(SeekFrom::Start(len - FOOTER_SIZE))?;
+    file.read_exact(&mut footer)?;
+    if &footer[10..] != FOOTER_MAGIC {
+        return Ok(None);
+    }
+    let backward_size = u32::from_le_bytes(footer[4..8].try_into().unwrap());
+    let index_size = (backward_size as u64 + 1) * 4;
+    let Some(index_start) = (len - FOOTER_SIZE).checked_sub(index_size) else {
+        return Ok(None);
+    };
+    let mut index = vec![0; index_size as usize];
+    file.seek(SeekFrom::Start(index_start))?;
+    file.read_exact(&mut index)?;
+
+    let mut bytes = index.into_iter();
+    if bytes.next() != Some(0) {
+        return Ok(None);
+    }
+    let Some(count) = read_varint(&mut bytes) else {
+        return Ok(None);
+    };
+    let blocks = (0..count)
+        .map(|_| {
+            Some(Block {
+                unpadded: read_varint(&mut bytes)?,
+                uncompressed: read_varint(&mut bytes)?,
+            })
+        })
+        .collect::<Option<Vec<_>>>();
+    // Anything between the blocks and the index is another stream
+    Ok(blocks.filter(|blocks| {
+        HEADER_SIZE + blocks.iter().map(Block::padded).sum::<u64>() == index_start
+    }))
+}
+
+/// An index and footer for a stream holding just these blocks
+fn index_and_footer(blocks: &[Block], stream_flags: [u8; 2]) -> Vec<u8> {
+    let mut index = vec![0];
+    write_varint(&mut index, blocks.len() as u64);
+    for block in blocks {
+        write_varint(&mut index, block.unpadded);
+        write_varint(&mut index, block.uncompressed);
+    }
+    index.resize(index.len().next_multiple_of(4), 0);
+    index.extend(crc32(&index));
+
+    let backward_size = (index.len() as u32 / 4 - 1).to_le_bytes();
+    let mut flagged = backward_size.to_vec();
+    flagged.extend(stream_flags);
+    index.extend(crc32(&flagged));
+    index.extend(flagged);
+    index.extend(FOOTER_MAGIC);
+    index
+}
+
+/// Starts decoding at the block holding byte `target` of the decompressed
+/// trace, by feeding the decoder the file's header, the blocks from there
+/// on, and an index rebuilt for only those blocks. Returns the stream and the
+/// decompressed offset it starts at, or None if there is no block to skip or
+/// the file is not a single indexed stream.
+pub fn seek(file: &mut fs::File, target: u64) -> io::Result<Option<(impl Read + Send, u64)>> {
+    let Some(blocks) = read_index(file)? else {
+        return Ok(None);
+    };
+    let mut start = HEADER_SIZE;
+    let mut position = 0;
+    let mut first = blocks.len();
+    for (idx, block) in blocks.iter().enumerate() {
+        if position + block.uncompressed > target {
+            first = idx;
+            break;
+        }
+        start += block.padded();
+        position += block.uncompressed;
+    }
+    if first == 0 {
+        return Ok(None);
+    }
+
+    let mut header = vec![0; HEADER_SIZE as usize];
+    file.seek(SeekFrom::Start(0))?;
+    file.read_exact(&mut header)?;
+    let stream_flags = [header[6], header[7]];
+    let rest = &blocks[first..];
+    let rest_size = rest.iter().map(Block::padded).sum();
+    let tail = index_and_footer(rest, stream_flags);
+
+    file.seek(SeekFrom::Start(start))?;
+    let stream = Cursor::new(header)
+        .chain(file.try_clone()?.take(rest_size))
+        .chain(Cursor::new(tail));
+    Ok(Some((stream, position)))
+}